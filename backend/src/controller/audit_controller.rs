@@ -0,0 +1,166 @@
+use axum::{
+    extract::{Path, Query, State}, http::StatusCode, Json
+};
+use bson::{doc, oid::ObjectId, Document};
+use futures::TryStreamExt;
+use mongodb::{Collection, Database};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use utoipa::{IntoParams, ToSchema};
+use crate::middleware::auth_middleware::Claims;
+use crate::models::audit_model::{AuditAction, AuditEvent, FieldChange};
+use crate::routes::openapi::{ErrorResponse, ObjectIdSchema};
+use crate::utils::authorization::{authorize_task, Action};
+
+const DEFAULT_PAGE_SIZE: i64 = 50;
+const MAX_PAGE_SIZE: i64 = 100;
+
+#[derive(Deserialize, IntoParams)]
+pub struct AuditListQuery {
+    // next_cursor of the previous page
+    pub after: Option<String>,
+    // 1 to 100, 50 by default
+    pub limit: Option<i64>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct FieldChangeResponse {
+    pub field: String,
+    // null when the field was not set
+    pub before: Value,
+    pub after: Value,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct AuditEventResponse {
+    #[schema(value_type = ObjectIdSchema)]
+    pub event_id: ObjectId,
+    pub action: AuditAction,
+    pub actor_id: String,
+    pub actor_name: String,
+    #[schema(value_type = Option<ObjectIdSchema>)]
+    pub task_id: Option<ObjectId>,
+    pub changes: Vec<FieldChangeResponse>,
+    pub request_id: Option<String>,
+    // RFC 3339
+    pub created_at: String,
+}
+
+// Audit events newest first, pass next_cursor as `after` to get the next page
+#[derive(Serialize, ToSchema)]
+pub struct AuditPage {
+    pub events: Vec<AuditEventResponse>,
+    pub next_cursor: Option<String>,
+}
+
+fn change_response(change: FieldChange) -> FieldChangeResponse {
+    FieldChangeResponse {
+        field: change.field,
+        before: change.before.into_relaxed_extjson(),
+        after: change.after.into_relaxed_extjson(),
+    }
+}
+
+fn event_response(event_id: ObjectId, event: AuditEvent) -> AuditEventResponse {
+    AuditEventResponse {
+        event_id,
+        action: event.action,
+        actor_id: event.actor_id,
+        actor_name: event.actor_name,
+        task_id: event.task_id,
+        changes: event.changes.into_iter().map(change_response).collect(),
+        request_id: event.request_id,
+        created_at: event.created_at.try_to_rfc3339_string().unwrap_or_default(),
+    }
+}
+
+// A page of the audit events matching the filter
+async fn audit_page(db: &Database, mut filter: Document, query: AuditListQuery) -> Result<AuditPage, (StatusCode, String)> {
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+    if !(1..=MAX_PAGE_SIZE).contains(&limit) {
+        return Err((StatusCode::BAD_REQUEST, format!("limit must be between 1 and {}", MAX_PAGE_SIZE)));
+    }
+    if let Some(after) = query.after.as_deref() {
+        let after = ObjectId::parse_str(after)
+            .map_err(|_| (StatusCode::BAD_REQUEST, "Invalid cursor".to_string()))?;
+        filter.insert("_id", doc! { "$lt": after });
+    }
+
+    let collection: Collection<AuditEvent> = db.collection("audit");
+    // one more than asked tells whether there is a next page
+    let mut events: Vec<AuditEvent> = match collection.find(filter).sort(doc! { "_id": -1 }).limit(limit + 1).await {
+        Ok(cursor) => cursor.try_collect().await,
+        Err(e) => Err(e),
+    }
+    .map_err(|e| {
+        tracing::error!("Error while listing the audit events: {}", e);
+        (StatusCode::INTERNAL_SERVER_ERROR, "Internal Server Error".to_string())
+    })?;
+
+    let next_cursor = if events.len() as i64 > limit {
+        events.truncate(limit as usize);
+        events.last().and_then(|event| event.audit_id).map(|id| id.to_hex())
+    } else {
+        None
+    };
+
+    Ok(AuditPage {
+        events: events
+            .into_iter()
+            .filter_map(|event| event.audit_id.map(|id| event_response(id, event)))
+            .collect(),
+        next_cursor,
+    })
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/tasks/{task_id}/history",
+    tag = "audit",
+    security(("bearer" = ["tasks:read"])),
+    params(
+        ("task_id" = String, Path, description = "Hex ObjectId of the task"),
+        AuditListQuery,
+    ),
+    responses(
+        (status = 200, description = "A page of the changes to the task, newest first", body = AuditPage),
+        (status = 400, description = "Invalid input", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Missing the tasks:read scope, or no right on the task", body = ErrorResponse),
+        (status = 404, description = "Not found", body = ErrorResponse),
+        (status = 429, description = "Too many requests", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    ),
+)]
+pub async fn task_history(
+    State(db): State<Database>,
+    claims: Claims,
+    Path(task_id): Path<String>,
+    Query(query): Query<AuditListQuery>,
+) -> Result<Json<AuditPage>, (StatusCode, String)> {
+    let (obj_id, _) = authorize_task(&db, &task_id, &claims.user_id, Action::View).await?;
+    Ok(Json(audit_page(&db, doc! { "task_id": obj_id }, query).await?))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/me/activity",
+    tag = "audit",
+    security(("bearer" = ["user:read"])),
+    params(AuditListQuery),
+    responses(
+        (status = 200, description = "A page of the changes made by the current user, newest first", body = AuditPage),
+        (status = 400, description = "Invalid input", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Missing the user:read scope", body = ErrorResponse),
+        (status = 429, description = "Too many requests", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    ),
+)]
+pub async fn my_activity(
+    State(db): State<Database>,
+    claims: Claims,
+    Query(query): Query<AuditListQuery>,
+) -> Result<Json<AuditPage>, (StatusCode, String)> {
+    Ok(Json(audit_page(&db, doc! { "actor_id": &claims.user_id }, query).await?))
+}
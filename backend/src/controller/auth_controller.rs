@@ -1,65 +1,136 @@
-use crate::models::user_model::User;
+use crate::models::{audit_model::{AuditAction, FieldChange}, user_model::User};
 use axum::{
     Json,
     extract::{State},
     http::{StatusCode},
 };
-use bcrypt::{DEFAULT_COST, hash, verify};
 use bson::{
     doc,
     oid::ObjectId,
+    Bson,
 };
-use jsonwebtoken::{encode, EncodingKey, Header};
+use jsonwebtoken::jwk::JwkSet;
 use mongodb::{Collection, Database};
 use serde::{Deserialize, Serialize};
-use std::{env};
+use utoipa::ToSchema;
+use std::sync::Arc;
 use chrono::{Utc};
-use crate::middleware::auth_middleware::Claims;
+use crate::middleware::{auth_middleware::Claims, request_id_middleware::RequestId, scope_middleware::requested_scopes};
+use crate::routes::openapi::{ErrorResponse, ObjectIdSchema};
+use crate::utils::{audit::{record, Actor}, keyring::Keyring, password::Passwords, state::AppState};
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 pub struct RegisterRequest {
     username: String,
     email: String,
     password: String,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 pub struct LoginRequest {
     email: String,
     password: String,
+    // limits the token to these scopes, e.g. ["tasks:read"] for a read-only
+    // integration, every scope when absent
+    #[serde(default)]
+    scopes: Option<Vec<String>>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct JWTClaims {
     pub user_id: String,
     pub username: String,
+    pub scopes: Vec<String>,
     pub exp: usize,
     pub iat: usize,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 pub struct AuthResponse {
-    msg: String,
+    pub msg: String,
     #[serde(skip_serializing_if = "Option::is_none")]
-    id: Option<String>,
+    pub id: Option<String>,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 pub struct UserData {
     pub username: String,
     pub email: String,
+    #[schema(value_type = Vec<ObjectIdSchema>)]
     pub tasks: Vec<ObjectId>, 
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 pub struct LoginResponse {
-    msg: String,
-    user_id: String,
-    token: String,
+    pub msg: String,
+    pub user_id: String,
+    pub token: String,
+}
+
+// login_attempts_total{method, outcome}, a 4xx is a failed login and a 5xx an error
+pub fn record_login<T, E>(method: &'static str, result: &Result<T, (StatusCode, E)>) {
+    let outcome = match result {
+        Ok(_) => "success",
+        Err((status, _)) if status.is_client_error() => "failure",
+        Err(_) => "error",
+    };
+    metrics::counter!("login_attempts_total", "method" => method, "outcome" => outcome).increment(1);
+}
+
+// Signs the RustManager token for a user, shared by the password and the SSO login
+pub fn issue_token(state: &AppState, user: &User, scopes: Vec<String>) -> Result<String, jsonwebtoken::errors::Error> {
+    let now = Utc::now();
+    let exp = now + chrono::Duration::hours(state.config.auth.token_expiry_hours);
+
+    // jwt claims 
+    let claims = JWTClaims{
+        user_id: user.user_id.map(|id| id.to_string()).unwrap_or_default(),
+        username: user.username.clone(),
+        scopes,
+        exp: exp.timestamp() as usize,
+        iat: now.timestamp() as usize,
+    };
+
+    state.keyring.sign(&claims)
 }
 
+// Upgrades a bcrypt (or outdated Argon2) hash after a successful login and tells
+// whether the new hash was stored, the login itself does not fail if this does
+async fn rehash_password(passwords: &Arc<Passwords>, collection: &Collection<User>, user: &User, password: &str) -> bool {
+    let hashed = match passwords.spawn_hash(password).await {
+        Ok(hashed) => hashed,
+        Err(e) => {
+            tracing::error!("Error in rehashing the password, {}", e);
+            return false;
+        }
+    };
+
+    if let Err(e) = collection
+        .update_one(doc! { "_id": user.user_id }, doc! { "$set": { "password": hashed } })
+        .await
+    {
+        tracing::error!("Error while storing the rehashed password: {}", e);
+        return false;
+    }
+    true
+}
+
+#[utoipa::path(
+    post,
+    path = "/user/register",
+    tag = "auth",
+    request_body = RegisterRequest,
+    responses(
+        (status = 200, description = "User created, `id` is the new user id", body = AuthResponse),
+        (status = 400, description = "Missing fields, weak password or email already taken", body = AuthResponse),
+        (status = 429, description = "Too many requests", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = AuthResponse),
+    ),
+)]
 pub async fn register(
     State(db): State<Database>,
+    State(passwords): State<Arc<Passwords>>,
+    request_id: RequestId,
     Json(payload): Json<RegisterRequest>,
 ) -> Result<Json<AuthResponse>, (StatusCode, Json<AuthResponse>)> {
     let collection: Collection<User> = db.collection("user");
@@ -74,6 +145,16 @@ pub async fn register(
         ));
     };
 
+    if let Err(msg) = passwords.check_policy(&payload.password) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(AuthResponse {
+                msg,
+                id: None,
+            }),
+        ));
+    }
+
     let filter = doc! {
         "email": &payload.email
     };
@@ -90,8 +171,8 @@ pub async fn register(
                 ));
             }
         }
-        Err(_) => {
-            println!("Error while checking the existence of the email");
+        Err(e) => {
+            tracing::error!("Error while checking the existence of the email: {}", e);
             return Err((
                 StatusCode::INTERNAL_SERVER_ERROR,
                 Json(AuthResponse {
@@ -102,10 +183,10 @@ pub async fn register(
         }
     }
 
-    let hashed = match hash(&payload.password, DEFAULT_COST) {
+    let hashed = match passwords.spawn_hash(&payload.password).await {
         Ok(hashed) => hashed,
         Err(e) => {
-            println!("Error in hashing the password, {}", e);
+            tracing::error!("Error in hashing the password, {}", e);
             return Err((
                 StatusCode::INTERNAL_SERVER_ERROR,
                 Json(AuthResponse {
@@ -116,44 +197,73 @@ pub async fn register(
         }
     };
 
-    let new_user = User {
+    let mut new_user = User {
         user_id: None,
         username: payload.username,
         email: payload.email,
         password: hashed,
         tasks: vec![],
+        notification_preferences: Default::default(),
     };
 
-    match collection.insert_one(new_user).await {
+    match collection.insert_one(&new_user).await {
         Ok(user_created) => {
-            return Ok(Json(AuthResponse {
+            new_user.user_id = user_created.inserted_id.as_object_id();
+            // the password is never part of the audit trail
+            let changes = vec![
+                FieldChange { field: "username".to_string(), before: Bson::Null, after: new_user.username.clone().into() },
+                FieldChange { field: "email".to_string(), before: Bson::Null, after: new_user.email.clone().into() },
+            ];
+            record(&db, &Actor::user(&new_user, request_id), AuditAction::UserRegistered, None, changes).await;
+
+            Ok(Json(AuthResponse {
                 msg: "User created Successfully".to_string(),
                 id: Some(user_created.inserted_id.to_string()),
-            }));
+            }))
         }
         Err(e) => {
-            println!("There is some error in inserting the new user: {}", e);
-            return Err((
+            tracing::error!("There is some error in inserting the new user: {}", e);
+            Err((
                 StatusCode::INTERNAL_SERVER_ERROR,
                 Json(AuthResponse {
                     msg: "Internal Server Error".to_string(),
                     id: None,
                 }),
-            ));
+            ))
         }
     }
 }
 
+#[utoipa::path(
+    post,
+    path = "/user/login",
+    tag = "auth",
+    request_body = LoginRequest,
+    responses(
+        (status = 200, description = "Signed token for the user", body = LoginResponse),
+        (status = 400, description = "Missing fields, unknown scope, wrong password or an SSO only account", body = AuthResponse),
+        (status = 401, description = "No user with this email", body = AuthResponse),
+        (status = 403, description = "Password login is disabled", body = AuthResponse),
+        (status = 429, description = "Too many requests", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = AuthResponse),
+    ),
+)]
 pub async fn login(
-    State(db): State<Database>,
+    State(state): State<AppState>,
+    request_id: RequestId,
     Json(payload): Json<LoginRequest>,
 ) -> Result<Json<LoginResponse>, (StatusCode, Json<AuthResponse>)> {
-    let jwt_secret: String = env::var("JWT_SECRET")
-        .expect("JWT_SECRET must be set");
-
-    const TOKEN_EXPIRY: i64 = 24; //24hrs
+    if !state.config.auth.password_login_enabled {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(AuthResponse {
+                msg: "Password login is disabled, sign in with SSO".to_string(),
+                id: None,
+            }),
+        ));
+    }
 
-    let collection: Collection<User> = db.collection("user");
+    let collection: Collection<User> = state.db.collection("user");
     if payload.email.is_empty() || payload.password.is_empty() {
         return Err((
             StatusCode::BAD_REQUEST,
@@ -163,87 +273,109 @@ pub async fn login(
             }),
         ));
     };
+    let scopes = requested_scopes(payload.scopes.as_deref())
+        .map_err(|msg| (StatusCode::BAD_REQUEST, Json(AuthResponse { msg, id: None })))?;
 
     let filter = doc! {
         "email": &payload.email
     };
 
-    match collection.find_one(filter).await {
-        Ok(Some(user_found)) => match verify(&payload.password, &user_found.password) {
-            Ok(true) => {
-                let now = Utc::now();
-                let exp = now + chrono::Duration::hours(TOKEN_EXPIRY);
-
-                // jwt claims 
-                let claims = JWTClaims{
-                    user_id: user_found.user_id.map(|id| id.to_string()).unwrap_or_default(),
-                    username: user_found.username.clone(),
-                    exp: exp.timestamp() as usize,
-                    iat: now.timestamp() as usize,
-                };
-
-                match encode(
-                    &Header::default(), &claims, &EncodingKey::from_secret(jwt_secret.as_ref()) ){
+    let result = match collection.find_one(filter).await {
+        // accounts provisioned through SSO have no password
+        Ok(Some(user_found)) if user_found.password.is_empty() => {
+            Err((
+                StatusCode::BAD_REQUEST,
+                Json(AuthResponse {
+                    msg: "This account signs in with SSO".to_string(),
+                    id: None,
+                }),
+            ))
+        }
+        Ok(Some(user_found)) => match state.passwords.spawn_verify(&payload.password, &user_found.password).await {
+            Ok(result) if result.valid => {
+                let actor = Actor::user(&user_found, request_id);
+                if result.needs_rehash && rehash_password(&state.passwords, &collection, &user_found, &payload.password).await {
+                    record(&state.db, &actor, AuditAction::PasswordRehashed, None, vec![]).await;
+                }
+
+                match issue_token(&state, &user_found, scopes) {
                         Ok(token) => {
-                            return Ok(Json(LoginResponse{
+                            record(&state.db, &actor, AuditAction::UserLoggedIn, None, vec![]).await;
+                            Ok(Json(LoginResponse{
                                 msg: "Login Successful".to_string(),
                                 user_id: user_found.user_id.map(|id| id.to_string()).unwrap_or_default(),
                                 token,
-                            }));
+                            }))
                         } Err(e) => {
-                            println!("Error while encoding JWT: {}", e);
-                            return Err((
+                            tracing::error!("Error while encoding JWT: {}", e);
+                            Err((
                                 StatusCode::INTERNAL_SERVER_ERROR,
                                 Json(AuthResponse {
                                     msg: "Internal Server Error".to_string(),
                                     id: None,
                                 }),
-                            ));
+                            ))
                         }
                     }
 
             }
-            Ok(false) => {
-                return Err((
+            Ok(_) => {
+                Err((
                     StatusCode::BAD_REQUEST,
                     Json(AuthResponse {
                         msg: "Wrong Password".to_string(),
                         id: None,
                     }),
-                ));
+                ))
             }
             Err(_) => {
-                return Err((
+                Err((
                     StatusCode::INTERNAL_SERVER_ERROR,
                     Json(AuthResponse {
                         msg: "Password Verification Failed".to_string(),
                         id: None,
                     }),
-                ));
+                ))
             }
         },
         Ok(None) => {
-            return Err((
+            Err((
                 StatusCode::UNAUTHORIZED,
                 Json(AuthResponse {
                     msg: "User Not Found".to_string(),
                     id: None,
                 }),
-            ));
+            ))
         }
         Err(e) => {
-            println!("Error while searching for email: {}", e);
-            return Err((
+            tracing::error!("Error while searching for email: {}", e);
+            Err((
                 StatusCode::INTERNAL_SERVER_ERROR,
                 Json(AuthResponse {
                     msg: "Password Verification Failed".to_string(),
                     id: None,
                 }),
-            ));
+            ))
         }
-    }
+    };
+
+    record_login("password", &result);
+    result
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/v1/me",
+    tag = "auth",
+    security(("bearer" = ["user:read"])),
+    responses(
+        (status = 200, description = "The current user", body = UserData),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Missing the user:read scope", body = ErrorResponse),
+        (status = 404, description = "Not found", body = AuthResponse),
+        (status = 500, description = "Internal server error", body = AuthResponse),
+    ),
+)]
 pub async fn get_user_data(
     State(db): State<Database>,
     claims: Claims,
@@ -268,28 +400,38 @@ pub async fn get_user_data(
 
     match collection.find_one(filter).await {
         Ok(Some(user_found))  => {
-            return Ok(Json(UserData {
+            Ok(Json(UserData {
                 username: user_found.username,
                 email: user_found.email,
                 tasks: user_found.tasks, 
             }))
         } Ok(None) => {
-            return Err((
+            Err((
                 StatusCode::NOT_FOUND,
                 Json(AuthResponse {
                     msg: "User not found".to_string(),
                     id: None,
                 }),
-            ));
+            ))
         } Err(e) => {
-            println!("Error while fetching user data: {}", e);
-            return Err((
+            tracing::error!("Error while fetching user data: {}", e);
+            Err((
                 StatusCode::INTERNAL_SERVER_ERROR,
                 Json(AuthResponse {
                     msg: "Internal Server Error".to_string(),
                     id: None,
                 }),
-            ));
+            ))
         }
     }
-}
\ No newline at end of file
+}
+// Public keys other services use to verify our tokens
+#[utoipa::path(
+    get,
+    path = "/.well-known/jwks.json",
+    tag = "auth",
+    responses((status = 200, description = "JSON Web Key Set of the token signing keys", body = Object)),
+)]
+pub async fn jwks(State(keyring): State<Arc<Keyring>>) -> Json<JwkSet> {
+    Json(keyring.jwks())
+}
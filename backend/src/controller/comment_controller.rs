@@ -0,0 +1,349 @@
+use axum::{
+    extract::{Path, Query, State}, http::StatusCode, Json
+};
+use bson::{doc, oid::ObjectId, DateTime};
+use futures::TryStreamExt;
+use mongodb::{Collection, Database};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use crate::middleware::auth_middleware::Claims;
+use crate::models::{comment_model::Comment, mention_model::Mention, notification_model::NotificationKind, task_model::Task};
+use crate::routes::openapi::{ErrorResponse, ObjectIdSchema};
+use crate::utils::{
+    authorization::{authorize_task, Action},
+    config::CommentsConfig,
+    mentions::{notify_mentions, resolve_mentions},
+    notifications::{notify, watchers},
+    state::AppState,
+};
+
+const DEFAULT_PAGE_SIZE: i64 = 50;
+const MAX_PAGE_SIZE: i64 = 100;
+
+#[derive(Deserialize, ToSchema)]
+pub struct CommentRequest {
+    // Markdown
+    pub body: String,
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct CommentListQuery {
+    // next_cursor of the previous page
+    pub after: Option<String>,
+    // 1 to 100, 50 by default
+    pub limit: Option<i64>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct CommentResponse {
+    #[schema(value_type = ObjectIdSchema)]
+    pub comment_id: ObjectId,
+    #[schema(value_type = ObjectIdSchema)]
+    pub task_id: ObjectId,
+    pub author_id: String,
+    pub author_name: String,
+    // Markdown, null once the comment is deleted
+    pub body: Option<String>,
+    // the @usernames of the body that link to a user, the others are plain text
+    pub mentions: Vec<Mention>,
+    // RFC 3339
+    pub created_at: String,
+    pub edited_at: Option<String>,
+    pub deleted: bool,
+}
+
+// Comments oldest first, pass next_cursor as `after` to get the next page
+#[derive(Serialize, ToSchema)]
+pub struct CommentPage {
+    pub comments: Vec<CommentResponse>,
+    pub next_cursor: Option<String>,
+}
+
+fn comment_response(comment_id: ObjectId, comment: Comment) -> CommentResponse {
+    let deleted = comment.deleted_at.is_some();
+    CommentResponse {
+        comment_id,
+        task_id: comment.task_id,
+        author_id: comment.author_id,
+        author_name: comment.author_name,
+        body: if deleted { None } else { Some(comment.body) },
+        mentions: comment.mentions,
+        created_at: comment.created_at.try_to_rfc3339_string().unwrap_or_default(),
+        edited_at: comment.edited_at.and_then(|at| at.try_to_rfc3339_string().ok()),
+        deleted,
+    }
+}
+
+fn internal_error(context: &str, e: mongodb::error::Error) -> (StatusCode, String) {
+    tracing::error!("{}: {}", context, e);
+    (StatusCode::INTERNAL_SERVER_ERROR, "Internal Server Error".to_string())
+}
+
+fn check_body(body: &str, config: &CommentsConfig) -> Result<(), (StatusCode, String)> {
+    if body.trim().is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "Comment cannot be empty".to_string()));
+    }
+    if body.len() > config.max_length {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("Comment is longer than {} bytes", config.max_length),
+        ));
+    }
+    Ok(())
+}
+
+// Loads a comment of the task that the user wrote and can still change
+async fn own_comment(
+    db: &Database,
+    task_id: ObjectId,
+    comment_id: &str,
+    user_id: &str,
+    config: &CommentsConfig,
+) -> Result<(ObjectId, Comment), (StatusCode, String)> {
+    let obj_id = ObjectId::parse_str(comment_id)
+        .map_err(|_| (StatusCode::BAD_REQUEST, "Invalid Comment Id".to_string()))?;
+
+    let comment = db
+        .collection::<Comment>("comment")
+        .find_one(doc! { "_id": obj_id, "task_id": task_id })
+        .await
+        .map_err(|e| internal_error("Error while finding the comment", e))?
+        .filter(|comment| comment.deleted_at.is_none())
+        .ok_or_else(|| (StatusCode::NOT_FOUND, "There is no comment with this id".to_string()))?;
+
+    if comment.author_id != user_id {
+        return Err((StatusCode::FORBIDDEN, "Only the author can change this comment".to_string()));
+    }
+    let age_ms = DateTime::now().timestamp_millis() - comment.created_at.timestamp_millis();
+    if age_ms > (config.edit_window_secs as i64).saturating_mul(1000) {
+        return Err((StatusCode::FORBIDDEN, "The comment can no longer be changed".to_string()));
+    }
+    Ok((obj_id, comment))
+}
+
+async fn count_comment(db: &Database, task_id: ObjectId, by: i64) {
+    if let Err(e) = db
+        .collection::<Task>("task")
+        .update_one(doc! { "_id": task_id }, doc! { "$inc": { "comment_count": by } })
+        .await
+    {
+        tracing::error!("Failed to update the comment count of the task: {}", e);
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/tasks/{task_id}/comments",
+    tag = "comments",
+    security(("bearer" = ["tasks:read"])),
+    params(
+        ("task_id" = String, Path, description = "Hex ObjectId of the task"),
+        CommentListQuery,
+    ),
+    responses(
+        (status = 200, description = "A page of the task's comments, deleted ones as tombstones", body = CommentPage),
+        (status = 400, description = "Invalid input", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Missing the tasks:read scope, or no access to the task", body = ErrorResponse),
+        (status = 404, description = "Not found", body = ErrorResponse),
+        (status = 429, description = "Too many requests", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    ),
+)]
+pub async fn list_comments(
+    State(db): State<Database>,
+    claims: Claims,
+    Path(task_id): Path<String>,
+    Query(query): Query<CommentListQuery>,
+) -> Result<Json<CommentPage>, (StatusCode, String)> {
+    let (task_obj_id, _) = authorize_task(&db, &task_id, &claims.user_id, Action::View).await?;
+
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+    if !(1..=MAX_PAGE_SIZE).contains(&limit) {
+        return Err((StatusCode::BAD_REQUEST, format!("limit must be between 1 and {}", MAX_PAGE_SIZE)));
+    }
+
+    let mut filter = doc! { "task_id": task_obj_id };
+    if let Some(after) = query.after.as_deref() {
+        let after = ObjectId::parse_str(after)
+            .map_err(|_| (StatusCode::BAD_REQUEST, "Invalid cursor".to_string()))?;
+        filter.insert("_id", doc! { "$gt": after });
+    }
+
+    let collection: Collection<Comment> = db.collection("comment");
+    // one more than asked tells whether there is a next page
+    let mut comments: Vec<Comment> = match collection.find(filter).sort(doc! { "_id": 1 }).limit(limit + 1).await {
+        Ok(cursor) => cursor.try_collect().await,
+        Err(e) => Err(e),
+    }
+    .map_err(|e| internal_error("Error while listing the comments", e))?;
+
+    let next_cursor = if comments.len() as i64 > limit {
+        comments.truncate(limit as usize);
+        comments.last().and_then(|comment| comment.comment_id).map(|id| id.to_hex())
+    } else {
+        None
+    };
+
+    Ok(Json(CommentPage {
+        comments: comments
+            .into_iter()
+            .filter_map(|comment| comment.comment_id.map(|id| comment_response(id, comment)))
+            .collect(),
+        next_cursor,
+    }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/tasks/{task_id}/comments",
+    tag = "comments",
+    security(("bearer" = ["tasks:write"])),
+    params(("task_id" = String, Path, description = "Hex ObjectId of the task")),
+    request_body = CommentRequest,
+    responses(
+        (status = 200, description = "The posted comment", body = CommentResponse),
+        (status = 400, description = "Invalid input, or an empty or too long comment", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Missing the tasks:write scope, or no comment right on the task", body = ErrorResponse),
+        (status = 404, description = "Not found", body = ErrorResponse),
+        (status = 429, description = "Too many requests", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    ),
+)]
+pub async fn create_comment(
+    State(state): State<AppState>,
+    claims: Claims,
+    Path(task_id): Path<String>,
+    Json(payload): Json<CommentRequest>,
+) -> Result<Json<CommentResponse>, (StatusCode, String)> {
+    let db = &state.db;
+    let (task_obj_id, task) = authorize_task(db, &task_id, &claims.user_id, Action::Comment).await?;
+    check_body(&payload.body, &state.config.comments)?;
+    let mentions = resolve_mentions(db, &task, &payload.body).await?;
+
+    let comment = Comment {
+        comment_id: None,
+        task_id: task_obj_id,
+        author_id: claims.user_id.clone(),
+        author_name: claims.username.clone(),
+        body: payload.body,
+        mentions,
+        created_at: DateTime::now(),
+        edited_at: None,
+        deleted_at: None,
+    };
+
+    let result = db
+        .collection::<Comment>("comment")
+        .insert_one(&comment)
+        .await
+        .map_err(|e| internal_error("Error while saving the comment", e))?;
+    let comment_id = result.inserted_id.as_object_id().ok_or_else(|| {
+        (StatusCode::INTERNAL_SERVER_ERROR, "Failed to get inserted comment ID".to_string())
+    })?;
+    count_comment(db, task_obj_id, 1).await;
+    notify_mentions(db, &comment.mentions, &[], &claims, task_obj_id, Some(comment_id)).await;
+    // the mentioned watchers already got a mentioned notification
+    let recipients = watchers(&task)
+        .into_iter()
+        .filter(|user_id| !comment.mentions.iter().any(|mention| mention.user_id == *user_id))
+        .collect();
+    notify(db, NotificationKind::Commented, recipients, task_obj_id, Some(comment_id), Some(&claims)).await;
+
+    Ok(Json(comment_response(comment_id, comment)))
+}
+
+#[utoipa::path(
+    patch,
+    path = "/api/v1/tasks/{task_id}/comments/{comment_id}",
+    tag = "comments",
+    security(("bearer" = ["tasks:write"])),
+    params(
+        ("task_id" = String, Path, description = "Hex ObjectId of the task"),
+        ("comment_id" = String, Path, description = "Hex ObjectId of the comment"),
+    ),
+    request_body = CommentRequest,
+    responses(
+        (status = 200, description = "The edited comment", body = CommentResponse),
+        (status = 400, description = "Invalid input, or an empty or too long comment", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Missing the tasks:write scope, not the author, or the edit window is over", body = ErrorResponse),
+        (status = 404, description = "Not found", body = ErrorResponse),
+        (status = 429, description = "Too many requests", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    ),
+)]
+pub async fn update_comment(
+    State(state): State<AppState>,
+    claims: Claims,
+    Path((task_id, comment_id)): Path<(String, String)>,
+    Json(payload): Json<CommentRequest>,
+) -> Result<Json<CommentResponse>, (StatusCode, String)> {
+    let db = &state.db;
+    let (task_obj_id, task) = authorize_task(db, &task_id, &claims.user_id, Action::Comment).await?;
+    check_body(&payload.body, &state.config.comments)?;
+    let (obj_id, mut comment) = own_comment(db, task_obj_id, &comment_id, &claims.user_id, &state.config.comments).await?;
+    let mentions = resolve_mentions(db, &task, &payload.body).await?;
+
+    let edited_at = DateTime::now();
+    db.collection::<Comment>("comment")
+        .update_one(
+            doc! { "_id": obj_id },
+            doc! { "$set": {
+                "body": &payload.body,
+                "mentions": bson::to_bson(&mentions).unwrap_or_default(),
+                "edited_at": edited_at,
+            } },
+        )
+        .await
+        .map_err(|e| internal_error("Error while editing the comment", e))?;
+    // only users newly mentioned by the edit are notified
+    notify_mentions(db, &mentions, &comment.mentions, &claims, task_obj_id, Some(obj_id)).await;
+
+    comment.body = payload.body;
+    comment.mentions = mentions;
+    comment.edited_at = Some(edited_at);
+    Ok(Json(comment_response(obj_id, comment)))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/tasks/{task_id}/comments/{comment_id}",
+    tag = "comments",
+    security(("bearer" = ["tasks:write"])),
+    params(
+        ("task_id" = String, Path, description = "Hex ObjectId of the task"),
+        ("comment_id" = String, Path, description = "Hex ObjectId of the comment"),
+    ),
+    responses(
+        (status = 204, description = "The comment was replaced by a tombstone"),
+        (status = 400, description = "Invalid input", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Missing the tasks:write scope, not the author, or the edit window is over", body = ErrorResponse),
+        (status = 404, description = "Not found", body = ErrorResponse),
+        (status = 429, description = "Too many requests", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    ),
+)]
+pub async fn delete_comment(
+    State(state): State<AppState>,
+    claims: Claims,
+    Path((task_id, comment_id)): Path<(String, String)>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let db = &state.db;
+    // taking back a comment only needs access to the task
+    let (task_obj_id, _) = authorize_task(db, &task_id, &claims.user_id, Action::View).await?;
+    let (obj_id, _) = own_comment(db, task_obj_id, &comment_id, &claims.user_id, &state.config.comments).await?;
+
+    db.collection::<Comment>("comment")
+        .update_one(
+            doc! { "_id": obj_id },
+            doc! { "$set": { "body": "", "mentions": [], "deleted_at": DateTime::now() } },
+        )
+        .await
+        .map_err(|e| internal_error("Error while deleting the comment", e))?;
+    count_comment(db, task_obj_id, -1).await;
+
+    Ok(StatusCode::NO_CONTENT)
+}
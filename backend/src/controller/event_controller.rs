@@ -0,0 +1,121 @@
+use axum::{
+    extract::State,
+    http::HeaderMap,
+    response::sse::{Event, KeepAlive, Sse},
+};
+use futures::{stream, Stream};
+use mongodb::Database;
+use std::{
+    collections::VecDeque,
+    convert::Infallible,
+    sync::{atomic::{AtomicBool, Ordering}, Arc},
+    time::Duration,
+};
+use tokio::sync::broadcast::{error::RecvError, Receiver};
+use crate::controller::task_controller::task_response;
+use crate::middleware::auth_middleware::Claims;
+use crate::routes::openapi::ErrorResponse;
+use crate::utils::{
+    authorization::{task_allows, Action},
+    events::{EventBus, Resume, TaskEvent},
+    state::AppState,
+};
+
+// how often an idle stream checks whether the server is shutting down
+const DRAIN_CHECK: Duration = Duration::from_secs(1);
+
+struct EventStream {
+    db: Database,
+    bus: Arc<EventBus>,
+    user_id: String,
+    draining: Arc<AtomicBool>,
+    replay: VecDeque<Arc<TaskEvent>>,
+    receiver: Receiver<Arc<TaskEvent>>,
+    // the client missed events and has to fetch its tasks again
+    reset: bool,
+}
+
+impl EventStream {
+    async fn next_event(&mut self) -> Option<Event> {
+        loop {
+            // streams end on shutdown so the drain doesn't wait for them
+            if self.draining.load(Ordering::SeqCst) {
+                return None;
+            }
+            if self.reset {
+                self.reset = false;
+                return Some(Event::default().event("reset").data("{}"));
+            }
+
+            let event = match self.replay.pop_front() {
+                Some(event) => event,
+                None => tokio::select! {
+                    received = self.receiver.recv() => match received {
+                        Ok(event) => event,
+                        Err(RecvError::Lagged(missed)) => {
+                            tracing::debug!("Event stream lagged behind by {} events", missed);
+                            self.reset = true;
+                            continue;
+                        }
+                        Err(RecvError::Closed) => return None,
+                    },
+                    _ = tokio::time::sleep(DRAIN_CHECK) => continue,
+                },
+            };
+
+            // errors are logged by the check, the event is skipped
+            if !task_allows(&self.db, &event.task, &self.user_id, Action::View).await.unwrap_or(false) {
+                continue;
+            }
+            match Event::default()
+                .id(self.bus.event_id(&event))
+                .event(event.kind.as_str())
+                .json_data(task_response(event.task_id, event.task.clone()))
+            {
+                Ok(sse_event) => return Some(sse_event),
+                Err(e) => tracing::error!("Failed to serialize the task event: {}", e),
+            }
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/events",
+    tag = "events",
+    security(("bearer" = ["tasks:read"])),
+    params(("Last-Event-ID" = Option<String>, Header, description = "Id of the last event received, to resume after a reconnect")),
+    responses(
+        (status = 200, description = "Server-Sent Events stream. `task.created`, `task.updated`, `task.deleted` and `task.restored` carry a TaskResponse of a task the user can see; `reset` means events were missed and the tasks have to be fetched again", content_type = "text/event-stream", body = String),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Missing the tasks:read scope", body = ErrorResponse),
+        (status = 429, description = "Too many requests", body = ErrorResponse),
+    ),
+)]
+pub async fn task_events(
+    State(state): State<AppState>,
+    claims: Claims,
+    headers: HeaderMap,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let last_event_id = headers.get("Last-Event-ID").and_then(|value| value.to_str().ok());
+    let (resume, receiver) = state.events.subscribe(last_event_id);
+
+    let (replay, reset) = match resume {
+        Resume::Replay(events) => (events.into(), false),
+        Resume::Reset => (VecDeque::new(), true),
+    };
+    let events = EventStream {
+        db: state.db.clone(),
+        bus: state.events.clone(),
+        user_id: claims.user_id,
+        draining: state.draining.clone(),
+        replay,
+        receiver,
+        reset,
+    };
+
+    let stream = stream::unfold(events, |mut events| async move {
+        events.next_event().await.map(|event| (Ok(event), events))
+    });
+    Sse::new(stream).keep_alive(KeepAlive::new().interval(Duration::from_secs(state.config.events.keep_alive_secs)))
+}
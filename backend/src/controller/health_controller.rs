@@ -0,0 +1,60 @@
+use axum::{extract::State, http::StatusCode, Json};
+use bson::doc;
+use serde_json::{json, Value};
+use std::{
+    sync::atomic::Ordering,
+    time::{Duration, Instant},
+};
+
+use crate::utils::state::AppState;
+
+// Liveness: the process is up and serving requests
+#[utoipa::path(
+    get,
+    path = "/healthz",
+    tag = "health",
+    responses((status = 200, description = "`{\"status\": \"ok\"}`", body = Object)),
+)]
+pub async fn healthz() -> Json<Value> {
+    Json(json!({"status": "ok"}))
+}
+
+// Readiness: every dependency answers, reported one by one
+#[utoipa::path(
+    get,
+    path = "/readyz",
+    tag = "health",
+    responses(
+        (status = 200, description = "Every dependency is up, with a status per dependency under `checks`", body = Object),
+        (status = 503, description = "A dependency is down or the server is draining", body = Object),
+    ),
+)]
+pub async fn readyz(State(state): State<AppState>) -> (StatusCode, Json<Value>) {
+    if state.draining.load(Ordering::SeqCst) {
+        return (StatusCode::SERVICE_UNAVAILABLE, Json(json!({"status": "draining"})));
+    }
+
+    let timeout = Duration::from_millis(state.config.database.ping_timeout_ms);
+
+    let start = Instant::now();
+    let mongodb = match tokio::time::timeout(timeout, state.db.run_command(doc! { "ping": 1 })).await {
+        Ok(Ok(_)) => json!({"status": "up", "latency_ms": start.elapsed().as_millis() as u64}),
+        // the details stay in the logs, this endpoint is unauthenticated
+        Ok(Err(e)) => {
+            tracing::warn!("Readiness check: MongoDB ping failed: {}", e);
+            json!({"status": "down"})
+        }
+        Err(_) => {
+            tracing::warn!("Readiness check: MongoDB ping timed out after {}ms", timeout.as_millis());
+            json!({"status": "down"})
+        }
+    };
+
+    let ready = mongodb["status"] == "up";
+    let status = if ready { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+
+    (status, Json(json!({
+        "status": if ready { "ready" } else { "not_ready" },
+        "checks": { "mongodb": mongodb },
+    })))
+}
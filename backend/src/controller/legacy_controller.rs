@@ -0,0 +1,83 @@
+// The unversioned routes, kept as deprecated aliases of /api/v1 until the sunset.
+// They only delegate, but have their own operations in the OpenAPI spec.
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use mongodb::Database;
+use std::sync::Arc;
+
+use crate::controller::{auth_controller::{self, AuthResponse, UserData}, task_controller::{self, TaskRequest, TaskResponse}};
+use crate::middleware::{auth_middleware::Claims, request_id_middleware::RequestId};
+use crate::utils::events::EventBus;
+
+#[utoipa::path(
+    get,
+    path = "/user",
+    tag = "legacy",
+    security(("bearer" = ["user:read"])),
+    responses((status = 200, description = "Alias of GET /api/v1/me", body = UserData)),
+)]
+#[deprecated = "use GET /api/v1/me"]
+pub async fn user(
+    db: State<Database>,
+    claims: Claims,
+) -> Result<Json<UserData>, (StatusCode, Json<AuthResponse>)> {
+    auth_controller::get_user_data(db, claims).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/task/create",
+    tag = "legacy",
+    security(("bearer" = ["tasks:write"])),
+    request_body = TaskRequest,
+    responses((status = 200, description = "Alias of POST /api/v1/tasks", body = TaskResponse)),
+)]
+#[deprecated = "use POST /api/v1/tasks"]
+pub async fn create_task(
+    db: State<Database>,
+    events: State<Arc<EventBus>>,
+    claims: Claims,
+    request_id: RequestId,
+    payload: Json<TaskRequest>,
+) -> Result<Json<TaskResponse>, (StatusCode, String)> {
+    task_controller::create_task(db, events, claims, request_id, payload).await
+}
+
+#[utoipa::path(
+    patch,
+    path = "/task/update/{task_id}",
+    tag = "legacy",
+    security(("bearer" = ["tasks:write"])),
+    params(("task_id" = String, Path, description = "Hex ObjectId of the task")),
+    request_body = TaskRequest,
+    responses((status = 200, description = "Alias of PUT /api/v1/tasks/{task_id}", body = TaskResponse)),
+)]
+#[deprecated = "use PUT /api/v1/tasks/{task_id}"]
+pub async fn update_task(
+    db: State<Database>,
+    events: State<Arc<EventBus>>,
+    claims: Claims,
+    request_id: RequestId,
+    task_id: Path<String>,
+    payload: Json<TaskRequest>,
+) -> Result<Json<TaskResponse>, (StatusCode, String)> {
+    task_controller::update_task(db, events, claims, request_id, task_id, payload).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/task/getAll",
+    tag = "legacy",
+    security(("bearer" = ["tasks:read"])),
+    responses((status = 200, description = "Alias of GET /api/v1/tasks", body = Vec<TaskResponse>)),
+)]
+#[deprecated = "use GET /api/v1/tasks"]
+pub async fn all_for_user(
+    db: State<Database>,
+    claims: Claims,
+) -> Result<Json<Vec<TaskResponse>>, (StatusCode, String)> {
+    task_controller::all_for_user(db, claims).await
+}
@@ -0,0 +1,442 @@
+use axum::{
+    extract::{Path, State}, http::StatusCode, Json
+};
+use bson::{doc, oid::ObjectId, DateTime};
+use futures::TryStreamExt;
+use mongodb::{Collection, Database};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use crate::middleware::auth_middleware::Claims;
+use crate::models::{invitation_model::Invitation, project_model::{Project, Role}, task_model::Task, user_model::User};
+use crate::routes::openapi::{ErrorResponse, ObjectIdSchema};
+use crate::utils::authorization::{authorize_project, Action};
+
+#[derive(Deserialize, ToSchema)]
+pub struct InviteRequest {
+    pub email: String,
+    // "viewer", "commenter" or "editor", a project has a single owner
+    pub role: Role,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct MemberRequest {
+    // "viewer", "commenter" or "editor"
+    pub role: Role,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct MemberResponse {
+    pub user_id: String,
+    pub username: String,
+    pub role: Role,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct InvitationResponse {
+    #[schema(value_type = ObjectIdSchema)]
+    pub invitation_id: ObjectId,
+    #[schema(value_type = ObjectIdSchema)]
+    pub project_id: ObjectId,
+    pub project_name: String,
+    pub email: String,
+    pub role: Role,
+    pub invited_by: String,
+    // "pending", "accepted" or "declined"
+    pub status: String,
+    // RFC 3339
+    pub created_at: String,
+}
+
+// Members get any role but owner, which is only the project's owner_id
+fn check_member_role(role: Role) -> Result<(), (StatusCode, String)> {
+    if role == Role::Owner {
+        return Err((StatusCode::BAD_REQUEST, "A member can't be made an owner of the project".to_string()));
+    }
+    Ok(())
+}
+
+fn invitation_response(invitation_id: ObjectId, invitation: Invitation) -> InvitationResponse {
+    InvitationResponse {
+        invitation_id,
+        project_id: invitation.project_id,
+        project_name: invitation.project_name,
+        email: invitation.email,
+        role: invitation.role,
+        invited_by: invitation.invited_by,
+        status: invitation.status,
+        created_at: invitation.created_at.try_to_rfc3339_string().unwrap_or_default(),
+    }
+}
+
+fn internal_error(context: &str, e: mongodb::error::Error) -> (StatusCode, String) {
+    tracing::error!("{}: {}", context, e);
+    (StatusCode::INTERNAL_SERVER_ERROR, "Internal Server Error".to_string())
+}
+
+async fn current_user(db: &Database, user_id: &str) -> Result<User, (StatusCode, String)> {
+    let obj_id = ObjectId::parse_str(user_id)
+        .map_err(|_| (StatusCode::BAD_REQUEST, "Invalid user ID".to_string()))?;
+
+    match db.collection::<User>("user").find_one(doc! { "_id": obj_id }).await {
+        Ok(Some(user)) => Ok(user),
+        Ok(None) => Err((StatusCode::NOT_FOUND, "User not found".to_string())),
+        Err(e) => Err(internal_error("Error while fetching the user", e)),
+    }
+}
+
+// Loads a pending invitation addressed to the current user
+async fn own_invitation(
+    db: &Database,
+    invitation_id: &str,
+    user_id: &str,
+) -> Result<(ObjectId, Invitation), (StatusCode, String)> {
+    let obj_id = ObjectId::parse_str(invitation_id)
+        .map_err(|_| (StatusCode::BAD_REQUEST, "Invalid Invitation Id".to_string()))?;
+    let user = current_user(db, user_id).await?;
+
+    let collection: Collection<Invitation> = db.collection("invitation");
+    match collection.find_one(doc! { "_id": obj_id }).await {
+        Ok(Some(invitation)) if invitation.email != user.email.to_lowercase() => Err((
+            StatusCode::FORBIDDEN,
+            "This invitation is for another user".to_string(),
+        )),
+        Ok(Some(invitation)) if invitation.status != "pending" => Err((
+            StatusCode::BAD_REQUEST,
+            format!("The invitation was already {}", invitation.status),
+        )),
+        Ok(Some(invitation)) => Ok((obj_id, invitation)),
+        Ok(None) => Err((StatusCode::NOT_FOUND, "There is no invitation with this id".to_string())),
+        Err(e) => Err(internal_error("Error while fetching the invitation", e)),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/projects/{project_id}/members",
+    tag = "members",
+    security(("bearer" = ["projects:read"])),
+    params(("project_id" = String, Path, description = "Hex ObjectId of the project")),
+    responses(
+        (status = 200, description = "The owner followed by the members", body = Vec<MemberResponse>),
+        (status = 400, description = "Invalid input", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Missing the projects:read scope, or no right on the project", body = ErrorResponse),
+        (status = 404, description = "Not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    ),
+)]
+pub async fn list_members(
+    State(db): State<Database>,
+    claims: Claims,
+    Path(project_id): Path<String>,
+) -> Result<Json<Vec<MemberResponse>>, (StatusCode, String)> {
+    let (_, project) = authorize_project(&db, &project_id, &claims.user_id, Action::View).await?;
+
+    let mut members = vec![(project.owner_id.clone(), Role::Owner)];
+    members.extend(project.members.iter().map(|member| (member.user_id.clone(), member.role)));
+
+    let ids: Vec<ObjectId> = members.iter().filter_map(|(user_id, _)| ObjectId::parse_str(user_id).ok()).collect();
+    let users: Vec<User> = match db.collection::<User>("user").find(doc! { "_id": { "$in": ids } }).await {
+        Ok(cursor) => cursor.try_collect().await,
+        Err(e) => Err(e),
+    }
+    .map_err(|e| internal_error("Error while fetching the members", e))?;
+
+    Ok(Json(members
+        .into_iter()
+        .map(|(user_id, role)| {
+            let username = users
+                .iter()
+                .find(|user| user.user_id.is_some_and(|id| id.to_hex() == user_id))
+                .map(|user| user.username.clone())
+                .unwrap_or_default();
+            MemberResponse { user_id, username, role }
+        })
+        .collect()))
+}
+
+#[utoipa::path(
+    patch,
+    path = "/api/v1/projects/{project_id}/members/{user_id}",
+    tag = "members",
+    security(("bearer" = ["projects:write"])),
+    params(
+        ("project_id" = String, Path, description = "Hex ObjectId of the project"),
+        ("user_id" = String, Path, description = "Id of the member"),
+    ),
+    request_body = MemberRequest,
+    responses(
+        (status = 204, description = "The role was changed"),
+        (status = 400, description = "Invalid input, the owner role, or the member is the project owner", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Missing the projects:write scope, or not an owner of the project", body = ErrorResponse),
+        (status = 404, description = "Not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    ),
+)]
+pub async fn update_member(
+    State(db): State<Database>,
+    claims: Claims,
+    Path((project_id, user_id)): Path<(String, String)>,
+    Json(payload): Json<MemberRequest>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    check_member_role(payload.role)?;
+    let (obj_id, project) = authorize_project(&db, &project_id, &claims.user_id, Action::Manage).await?;
+
+    if project.owner_id == user_id {
+        return Err((StatusCode::BAD_REQUEST, "The role of the project owner can't be changed".to_string()));
+    }
+
+    let result = db
+        .collection::<Project>("project")
+        .update_one(
+            doc! { "_id": obj_id, "members.user_id": &user_id },
+            doc! { "$set": { "members.$.role": payload.role.as_str() } },
+        )
+        .await
+        .map_err(|e| internal_error("Error while changing the member role", e))?;
+
+    if result.matched_count == 0 {
+        return Err((StatusCode::NOT_FOUND, "The user is not a member of this project".to_string()));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/projects/{project_id}/members/{user_id}",
+    tag = "members",
+    security(("bearer" = ["projects:write"])),
+    params(
+        ("project_id" = String, Path, description = "Hex ObjectId of the project"),
+        ("user_id" = String, Path, description = "Id of the member, members can remove themselves"),
+    ),
+    responses(
+        (status = 204, description = "The member was removed"),
+        (status = 400, description = "Invalid input, the owner role, or the member is the project owner", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Missing the projects:write scope, or not an owner of the project", body = ErrorResponse),
+        (status = 404, description = "Not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    ),
+)]
+pub async fn remove_member(
+    State(db): State<Database>,
+    claims: Claims,
+    Path((project_id, user_id)): Path<(String, String)>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    // leaving a project only needs access to it
+    let action = if user_id == claims.user_id { Action::View } else { Action::Manage };
+    let (obj_id, project) = authorize_project(&db, &project_id, &claims.user_id, action).await?;
+
+    if project.owner_id == user_id {
+        return Err((StatusCode::BAD_REQUEST, "The project owner can't be removed".to_string()));
+    }
+
+    let result = db
+        .collection::<Project>("project")
+        .update_one(doc! { "_id": obj_id }, doc! { "$pull": { "members": { "user_id": &user_id } } })
+        .await
+        .map_err(|e| internal_error("Error while removing the member", e))?;
+
+    if result.modified_count == 0 {
+        return Err((StatusCode::NOT_FOUND, "The user is not a member of this project".to_string()));
+    }
+
+    // without access they can't work on the project's tasks anymore
+    db.collection::<Task>("task")
+        .update_many(doc! { "project_id": obj_id }, doc! { "$pull": { "assignee_ids": &user_id } })
+        .await
+        .map_err(|e| internal_error("Error while unassigning the member", e))?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/projects/{project_id}/invitations",
+    tag = "members",
+    security(("bearer" = ["projects:write"])),
+    params(("project_id" = String, Path, description = "Hex ObjectId of the project")),
+    request_body = InviteRequest,
+    responses(
+        (status = 200, description = "The pending invitation", body = InvitationResponse),
+        (status = 400, description = "Invalid input, the owner role, already a member or already invited", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Missing the projects:write scope, or not an owner of the project", body = ErrorResponse),
+        (status = 404, description = "Not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    ),
+)]
+pub async fn invite_member(
+    State(db): State<Database>,
+    claims: Claims,
+    Path(project_id): Path<String>,
+    Json(payload): Json<InviteRequest>,
+) -> Result<Json<InvitationResponse>, (StatusCode, String)> {
+    let email = payload.email.trim().to_lowercase();
+    if email.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "The email can't be empty".to_string()));
+    }
+    check_member_role(payload.role)?;
+
+    let (obj_id, project) = authorize_project(&db, &project_id, &claims.user_id, Action::Manage).await?;
+
+    let invitee = db
+        .collection::<User>("user")
+        .find_one(doc! { "email": &email })
+        .await
+        .map_err(|e| internal_error("Error while searching for email", e))?;
+    if let Some(user_id) = invitee.and_then(|user| user.user_id)
+        && project.role_of(&user_id.to_hex()).is_some()
+    {
+        return Err((StatusCode::BAD_REQUEST, "The user is already a member of this project".to_string()));
+    }
+
+    let collection: Collection<Invitation> = db.collection("invitation");
+    let pending = collection
+        .find_one(doc! { "project_id": obj_id, "email": &email, "status": "pending" })
+        .await
+        .map_err(|e| internal_error("Error while checking the pending invitations", e))?;
+    if pending.is_some() {
+        return Err((StatusCode::BAD_REQUEST, "The user is already invited to this project".to_string()));
+    }
+
+    let invitation = Invitation {
+        invitation_id: None,
+        project_id: obj_id,
+        project_name: project.name,
+        email,
+        role: payload.role,
+        invited_by: claims.user_id,
+        status: "pending".to_string(),
+        created_at: DateTime::now(),
+    };
+
+    match collection.insert_one(&invitation).await {
+        Ok(result) => match result.inserted_id.as_object_id() {
+            Some(invitation_id) => Ok(Json(invitation_response(invitation_id, invitation))),
+            None => Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to get inserted invitation ID".to_string(),
+            )),
+        },
+        Err(e) => Err(internal_error("Error creating the invitation", e)),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/invitations",
+    tag = "members",
+    security(("bearer" = ["projects:read"])),
+    responses(
+        (status = 200, description = "Pending invitations sent to the current user's email", body = Vec<InvitationResponse>),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Missing the projects:read scope", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    ),
+)]
+pub async fn list_invitations(
+    State(db): State<Database>,
+    claims: Claims,
+) -> Result<Json<Vec<InvitationResponse>>, (StatusCode, String)> {
+    let user = current_user(&db, &claims.user_id).await?;
+
+    let collection: Collection<Invitation> = db.collection("invitation");
+    let filter = doc! { "email": user.email.to_lowercase(), "status": "pending" };
+    let invitations: Vec<Invitation> = match collection.find(filter).sort(doc! { "created_at": -1 }).await {
+        Ok(cursor) => cursor.try_collect().await,
+        Err(e) => Err(e),
+    }
+    .map_err(|e| internal_error("Error while listing the invitations", e))?;
+
+    Ok(Json(invitations
+        .into_iter()
+        .filter_map(|invitation| invitation.invitation_id.map(|id| invitation_response(id, invitation)))
+        .collect()))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/invitations/{invitation_id}/accept",
+    tag = "members",
+    security(("bearer" = ["projects:write"])),
+    params(("invitation_id" = String, Path, description = "Hex ObjectId of the invitation")),
+    responses(
+        (status = 200, description = "The accepted invitation, the user is now a member", body = InvitationResponse),
+        (status = 400, description = "Invalid input or the invitation is not pending", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Missing the projects:write scope, or the invitation is for another user", body = ErrorResponse),
+        (status = 404, description = "Not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    ),
+)]
+pub async fn accept_invitation(
+    State(db): State<Database>,
+    claims: Claims,
+    Path(invitation_id): Path<String>,
+) -> Result<Json<InvitationResponse>, (StatusCode, String)> {
+    let (obj_id, mut invitation) = own_invitation(&db, &invitation_id, &claims.user_id).await?;
+    let projects: Collection<Project> = db.collection("project");
+
+    // a member invited again gets the new role
+    let updated = projects
+        .update_one(
+            doc! { "_id": invitation.project_id, "members.user_id": &claims.user_id },
+            doc! { "$set": { "members.$.role": invitation.role.as_str() } },
+        )
+        .await
+        .map_err(|e| internal_error("Error while adding the member", e))?;
+
+    if updated.matched_count == 0 {
+        let added = projects
+            .update_one(
+                doc! { "_id": invitation.project_id, "owner_id": { "$ne": &claims.user_id } },
+                doc! { "$push": { "members": { "user_id": &claims.user_id, "role": invitation.role.as_str() } } },
+            )
+            .await
+            .map_err(|e| internal_error("Error while adding the member", e))?;
+        if added.matched_count == 0 {
+            return Err((StatusCode::NOT_FOUND, "The project no longer exists".to_string()));
+        }
+    }
+
+    set_invitation_status(&db, obj_id, "accepted").await?;
+    invitation.status = "accepted".to_string();
+    Ok(Json(invitation_response(obj_id, invitation)))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/invitations/{invitation_id}/decline",
+    tag = "members",
+    security(("bearer" = ["projects:write"])),
+    params(("invitation_id" = String, Path, description = "Hex ObjectId of the invitation")),
+    responses(
+        (status = 200, description = "The declined invitation", body = InvitationResponse),
+        (status = 400, description = "Invalid input or the invitation is not pending", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Missing the projects:write scope, or the invitation is for another user", body = ErrorResponse),
+        (status = 404, description = "Not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    ),
+)]
+pub async fn decline_invitation(
+    State(db): State<Database>,
+    claims: Claims,
+    Path(invitation_id): Path<String>,
+) -> Result<Json<InvitationResponse>, (StatusCode, String)> {
+    let (obj_id, mut invitation) = own_invitation(&db, &invitation_id, &claims.user_id).await?;
+
+    set_invitation_status(&db, obj_id, "declined").await?;
+    invitation.status = "declined".to_string();
+    Ok(Json(invitation_response(obj_id, invitation)))
+}
+
+async fn set_invitation_status(db: &Database, invitation_id: ObjectId, status: &str) -> Result<(), (StatusCode, String)> {
+    db.collection::<Invitation>("invitation")
+        .update_one(doc! { "_id": invitation_id }, doc! { "$set": { "status": status } })
+        .await
+        .map(|_| ())
+        .map_err(|e| internal_error("Error while updating the invitation", e))
+}
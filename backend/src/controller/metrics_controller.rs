@@ -0,0 +1,82 @@
+use axum::{
+    extract::State,
+    http::{header, HeaderMap, StatusCode},
+};
+use bson::{doc, Document};
+use futures::TryStreamExt;
+use ring::digest::{digest, SHA256};
+use std::{collections::BTreeSet, sync::Mutex, time::Duration};
+
+use crate::models::task_model::not_deleted;
+use crate::utils::state::AppState;
+
+const TASK_COUNT_TIMEOUT: Duration = Duration::from_secs(2);
+
+// The statuses with a gauge, the ones gone from the tasks are set back to 0
+static REPORTED_STATUSES: Mutex<BTreeSet<String>> = Mutex::new(BTreeSet::new());
+
+// Compares the digests so the time taken doesn't tell how much of the token matched
+fn token_matches(given: &str, expected: &str) -> bool {
+    let (given, expected) = (digest(&SHA256, given.as_bytes()), digest(&SHA256, expected.as_bytes()));
+    given.as_ref().iter().zip(expected.as_ref()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+// Prometheus scrape endpoint. When it is served on the public port a bearer token is required
+pub async fn metrics(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<String, (StatusCode, String)> {
+    let handle = state.metrics.as_ref()
+        .ok_or_else(|| (StatusCode::NOT_FOUND, "Metrics are disabled".to_string()))?;
+
+    if state.config.metrics.admin_bind_address.is_none() {
+        let expected = state.config.metrics.token.as_deref().map(|token| format!("Bearer {}", token));
+        let given = headers.get(header::AUTHORIZATION).and_then(|value| value.to_str().ok());
+        let valid = match (given, expected.as_deref()) {
+            (Some(given), Some(expected)) => token_matches(given, expected),
+            _ => false,
+        };
+        if !valid {
+            return Err((StatusCode::UNAUTHORIZED, "Invalid metrics token".to_string()));
+        }
+    }
+
+    // a slow or unreachable database must not stall the scrape
+    match tokio::time::timeout(TASK_COUNT_TIMEOUT, record_task_counts(&state)).await {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => tracing::error!("Failed to count the tasks by status: {}", e),
+        Err(_) => tracing::warn!("Counting the tasks by status timed out"),
+    }
+
+    handle.run_upkeep();
+    Ok(handle.render())
+}
+
+// Gauge of the number of tasks per status, refreshed on every scrape
+async fn record_task_counts(state: &AppState) -> Result<(), mongodb::error::Error> {
+    let pipeline = vec![
+        doc! { "$match": not_deleted(doc! {}) },
+        doc! { "$group": { "_id": "$status", "count": { "$sum": 1 } } },
+    ];
+    let mut cursor = state.db.collection::<Document>("task").aggregate(pipeline).await?;
+
+    let mut counts = Vec::new();
+    while let Some(group) = cursor.try_next().await? {
+        let status = group.get_str("_id").unwrap_or("unknown").to_string();
+        // $sum gives an int32 or, past its range, an int64
+        let count = group.get_i64("count").or_else(|_| group.get_i32("count").map(i64::from)).unwrap_or(0);
+        counts.push((status, count));
+    }
+
+    let mut reported = REPORTED_STATUSES.lock().unwrap();
+    for status in reported.iter() {
+        if !counts.iter().any(|(counted, _)| counted == status) {
+            metrics::gauge!("tasks", "status" => status.clone()).set(0.0);
+        }
+    }
+    for (status, count) in counts {
+        metrics::gauge!("tasks", "status" => status.clone()).set(count as f64);
+        reported.insert(status);
+    }
+    Ok(())
+}
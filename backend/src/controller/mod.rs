@@ -1,2 +1,13 @@
+pub mod audit_controller;
 pub mod auth_controller;
-pub mod task_controller;
\ No newline at end of file
+pub mod comment_controller;
+pub mod event_controller;
+pub mod health_controller;
+pub mod legacy_controller;
+pub mod member_controller;
+pub mod metrics_controller;
+pub mod notification_controller;
+pub mod oidc_controller;
+pub mod project_controller;
+pub mod task_controller;
+pub mod webhook_controller;
\ No newline at end of file
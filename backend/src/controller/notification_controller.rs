@@ -0,0 +1,268 @@
+use axum::{
+    extract::{Path, Query, State}, http::StatusCode, Json
+};
+use bson::{doc, oid::ObjectId};
+use futures::TryStreamExt;
+use mongodb::{Collection, Database};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use crate::middleware::auth_middleware::Claims;
+use crate::models::{
+    notification_model::{Notification, NotificationKind, NotificationPreferences}, user_model::User
+};
+use crate::routes::openapi::{ErrorResponse, ObjectIdSchema};
+
+const DEFAULT_PAGE_SIZE: i64 = 50;
+const MAX_PAGE_SIZE: i64 = 100;
+
+#[derive(Deserialize, IntoParams)]
+pub struct NotificationListQuery {
+    // only the unread ones
+    #[serde(default)]
+    pub unread: bool,
+    // next_cursor of the previous page
+    pub after: Option<String>,
+    // 1 to 100, 50 by default
+    pub limit: Option<i64>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct NotificationResponse {
+    #[schema(value_type = ObjectIdSchema)]
+    pub notification_id: ObjectId,
+    pub kind: NotificationKind,
+    #[schema(value_type = ObjectIdSchema)]
+    pub task_id: ObjectId,
+    #[schema(value_type = Option<ObjectIdSchema>)]
+    pub comment_id: Option<ObjectId>,
+    // null for reminders like due_soon
+    pub actor_id: Option<String>,
+    pub actor_name: Option<String>,
+    // RFC 3339
+    pub created_at: String,
+    pub read: bool,
+}
+
+// Notifications newest first, pass next_cursor as `after` to get the next page
+#[derive(Serialize, ToSchema)]
+pub struct NotificationPage {
+    pub notifications: Vec<NotificationResponse>,
+    pub next_cursor: Option<String>,
+    pub unread_count: u64,
+}
+
+fn notification_response(notification_id: ObjectId, notification: Notification) -> NotificationResponse {
+    NotificationResponse {
+        notification_id,
+        kind: notification.kind,
+        task_id: notification.task_id,
+        comment_id: notification.comment_id,
+        actor_id: notification.actor_id,
+        actor_name: notification.actor_name,
+        created_at: notification.created_at.try_to_rfc3339_string().unwrap_or_default(),
+        read: notification.read,
+    }
+}
+
+fn internal_error(context: &str, e: mongodb::error::Error) -> (StatusCode, String) {
+    tracing::error!("{}: {}", context, e);
+    (StatusCode::INTERNAL_SERVER_ERROR, "Internal Server Error".to_string())
+}
+
+fn user_filter(user_id: &str) -> Result<bson::Document, (StatusCode, String)> {
+    let obj_id = ObjectId::parse_str(user_id)
+        .map_err(|_| (StatusCode::BAD_REQUEST, "Invalid user ID".to_string()))?;
+    Ok(doc! { "_id": obj_id })
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/notifications",
+    tag = "notifications",
+    security(("bearer" = ["notifications:read"])),
+    params(NotificationListQuery),
+    responses(
+        (status = 200, description = "A page of the current user's notifications", body = NotificationPage),
+        (status = 400, description = "Invalid input", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Missing the notifications:read scope", body = ErrorResponse),
+        (status = 429, description = "Too many requests", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    ),
+)]
+pub async fn list_notifications(
+    State(db): State<Database>,
+    claims: Claims,
+    Query(query): Query<NotificationListQuery>,
+) -> Result<Json<NotificationPage>, (StatusCode, String)> {
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+    if !(1..=MAX_PAGE_SIZE).contains(&limit) {
+        return Err((StatusCode::BAD_REQUEST, format!("limit must be between 1 and {}", MAX_PAGE_SIZE)));
+    }
+
+    let mut filter = doc! { "user_id": &claims.user_id };
+    if query.unread {
+        filter.insert("read", false);
+    }
+    if let Some(after) = query.after.as_deref() {
+        let after = ObjectId::parse_str(after)
+            .map_err(|_| (StatusCode::BAD_REQUEST, "Invalid cursor".to_string()))?;
+        filter.insert("_id", doc! { "$lt": after });
+    }
+
+    let collection: Collection<Notification> = db.collection("notification");
+    // one more than asked tells whether there is a next page
+    let mut notifications: Vec<Notification> = match collection.find(filter).sort(doc! { "_id": -1 }).limit(limit + 1).await {
+        Ok(cursor) => cursor.try_collect().await,
+        Err(e) => Err(e),
+    }
+    .map_err(|e| internal_error("Error while listing the notifications", e))?;
+
+    let next_cursor = if notifications.len() as i64 > limit {
+        notifications.truncate(limit as usize);
+        notifications.last().and_then(|notification| notification.notification_id).map(|id| id.to_hex())
+    } else {
+        None
+    };
+
+    let unread_count = collection
+        .count_documents(doc! { "user_id": &claims.user_id, "read": false })
+        .await
+        .map_err(|e| internal_error("Error while counting the unread notifications", e))?;
+
+    Ok(Json(NotificationPage {
+        notifications: notifications
+            .into_iter()
+            .filter_map(|notification| notification.notification_id.map(|id| notification_response(id, notification)))
+            .collect(),
+        next_cursor,
+        unread_count,
+    }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/notifications/{notification_id}/read",
+    tag = "notifications",
+    security(("bearer" = ["notifications:write"])),
+    params(("notification_id" = String, Path, description = "Hex ObjectId of the notification")),
+    responses(
+        (status = 204, description = "The notification is marked as read"),
+        (status = 400, description = "Invalid input", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Missing the notifications:write scope", body = ErrorResponse),
+        (status = 404, description = "Not found", body = ErrorResponse),
+        (status = 429, description = "Too many requests", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    ),
+)]
+pub async fn mark_read(
+    State(db): State<Database>,
+    claims: Claims,
+    Path(notification_id): Path<String>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let obj_id = ObjectId::parse_str(&notification_id)
+        .map_err(|_| (StatusCode::BAD_REQUEST, "Invalid Notification Id".to_string()))?;
+
+    let result = db
+        .collection::<Notification>("notification")
+        .update_one(doc! { "_id": obj_id, "user_id": &claims.user_id }, doc! { "$set": { "read": true } })
+        .await
+        .map_err(|e| internal_error("Error while marking the notification as read", e))?;
+
+    // someone else's notification looks the same as a missing one
+    if result.matched_count == 0 {
+        return Err((StatusCode::NOT_FOUND, "There is no notification with this id".to_string()));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/notifications/read-all",
+    tag = "notifications",
+    security(("bearer" = ["notifications:write"])),
+    responses(
+        (status = 204, description = "Every notification of the current user is marked as read"),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Missing the notifications:write scope", body = ErrorResponse),
+        (status = 429, description = "Too many requests", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    ),
+)]
+pub async fn mark_all_read(
+    State(db): State<Database>,
+    claims: Claims,
+) -> Result<StatusCode, (StatusCode, String)> {
+    db.collection::<Notification>("notification")
+        .update_many(doc! { "user_id": &claims.user_id, "read": false }, doc! { "$set": { "read": true } })
+        .await
+        .map_err(|e| internal_error("Error while marking the notifications as read", e))?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/me/notification-preferences",
+    tag = "notifications",
+    security(("bearer" = ["notifications:read"])),
+    responses(
+        (status = 200, description = "Which kinds of notifications the current user gets", body = NotificationPreferences),
+        (status = 400, description = "Invalid input", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Missing the notifications:read scope", body = ErrorResponse),
+        (status = 404, description = "Not found", body = ErrorResponse),
+        (status = 429, description = "Too many requests", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    ),
+)]
+pub async fn get_preferences(
+    State(db): State<Database>,
+    claims: Claims,
+) -> Result<Json<NotificationPreferences>, (StatusCode, String)> {
+    let user = db
+        .collection::<User>("user")
+        .find_one(user_filter(&claims.user_id)?)
+        .await
+        .map_err(|e| internal_error("Error while finding the user", e))?
+        .ok_or_else(|| (StatusCode::NOT_FOUND, "User not found".to_string()))?;
+    Ok(Json(user.notification_preferences))
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/v1/me/notification-preferences",
+    tag = "notifications",
+    security(("bearer" = ["notifications:write"])),
+    request_body(content = NotificationPreferences, description = "Absent kinds are turned on"),
+    responses(
+        (status = 200, description = "The saved preferences", body = NotificationPreferences),
+        (status = 400, description = "Invalid input", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Missing the notifications:write scope", body = ErrorResponse),
+        (status = 404, description = "Not found", body = ErrorResponse),
+        (status = 429, description = "Too many requests", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    ),
+)]
+pub async fn update_preferences(
+    State(db): State<Database>,
+    claims: Claims,
+    Json(payload): Json<NotificationPreferences>,
+) -> Result<Json<NotificationPreferences>, (StatusCode, String)> {
+    let preferences = bson::to_bson(&payload).map_err(|e| {
+        tracing::error!("Failed to serialize the notification preferences: {}", e);
+        (StatusCode::INTERNAL_SERVER_ERROR, "Internal Server Error".to_string())
+    })?;
+
+    let result = db
+        .collection::<User>("user")
+        .update_one(user_filter(&claims.user_id)?, doc! { "$set": { "notification_preferences": preferences } })
+        .await
+        .map_err(|e| internal_error("Error while saving the notification preferences", e))?;
+
+    if result.matched_count == 0 {
+        return Err((StatusCode::NOT_FOUND, "User not found".to_string()));
+    }
+    Ok(Json(payload))
+}
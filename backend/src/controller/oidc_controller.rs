@@ -0,0 +1,185 @@
+use crate::controller::auth_controller::{issue_token, record_login, AuthResponse, LoginResponse};
+use crate::middleware::{request_id_middleware::RequestId, scope_middleware::{default_scopes, requested_scopes}};
+use crate::models::{audit_model::{AuditAction, FieldChange}, user_model::User};
+use crate::utils::{audit::{record, Actor}, state::AppState};
+use axum::{
+    Json,
+    extract::{Query, State},
+    http::StatusCode,
+    response::Redirect,
+};
+use bson::{doc, Bson};
+use mongodb::Collection;
+use serde::Deserialize;
+use utoipa::IntoParams;
+
+#[derive(Deserialize, IntoParams)]
+pub struct LoginQuery {
+    // comma separated scopes to limit the token to, e.g. "tasks:read", every scope when absent
+    scopes: Option<String>,
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct CallbackQuery {
+    code: Option<String>,
+    state: Option<String>,
+    error: Option<String>,
+}
+
+fn oidc_error(status: StatusCode, msg: &str) -> (StatusCode, Json<AuthResponse>) {
+    (
+        status,
+        Json(AuthResponse {
+            msg: msg.to_string(),
+            id: None,
+        }),
+    )
+}
+
+// Sends the browser to the identity provider
+#[utoipa::path(
+    get,
+    path = "/auth/oidc/login",
+    tag = "sso",
+    params(LoginQuery),
+    responses(
+        (status = 303, description = "Redirect to the identity provider"),
+        (status = 400, description = "Unknown scope", body = AuthResponse),
+        (status = 404, description = "SSO login is not configured", body = AuthResponse),
+        (status = 502, description = "The identity provider is unavailable", body = AuthResponse),
+    ),
+)]
+pub async fn oidc_login(
+    State(state): State<AppState>,
+    Query(query): Query<LoginQuery>,
+) -> Result<Redirect, (StatusCode, Json<AuthResponse>)> {
+    let oidc = state.oidc.as_ref()
+        .ok_or_else(|| oidc_error(StatusCode::NOT_FOUND, "SSO login is not configured"))?;
+
+    let requested: Option<Vec<String>> = query.scopes.map(|scopes| {
+        scopes.split(',').map(str::trim).filter(|scope| !scope.is_empty()).map(str::to_string).collect()
+    });
+    let scopes = requested_scopes(requested.as_deref()).map_err(|msg| oidc_error(StatusCode::BAD_REQUEST, &msg))?;
+
+    match oidc.authorization_url(&state.db, scopes).await {
+        Ok(url) => Ok(Redirect::to(&url)),
+        Err(e) => {
+            tracing::error!("Error while starting the SSO login: {}", e);
+            Err(oidc_error(StatusCode::BAD_GATEWAY, "Identity provider is unavailable"))
+        }
+    }
+}
+
+// The identity provider redirects back here with the authorization code
+#[utoipa::path(
+    get,
+    path = "/auth/oidc/callback",
+    tag = "sso",
+    params(CallbackQuery),
+    responses(
+        (status = 200, description = "Signed token for the linked or new user", body = LoginResponse),
+        (status = 400, description = "Invalid input", body = AuthResponse),
+        (status = 401, description = "The login failed at the identity provider", body = AuthResponse),
+        (status = 403, description = "No verified email in the ID token", body = AuthResponse),
+        (status = 404, description = "SSO login is not configured", body = AuthResponse),
+        (status = 500, description = "Internal server error", body = AuthResponse),
+    ),
+)]
+pub async fn oidc_callback(
+    State(state): State<AppState>,
+    request_id: RequestId,
+    Query(query): Query<CallbackQuery>,
+) -> Result<Json<LoginResponse>, (StatusCode, Json<AuthResponse>)> {
+    let result = complete_login(&state, request_id, query).await;
+    record_login("oidc", &result);
+    result
+}
+
+async fn complete_login(
+    state: &AppState,
+    request_id: RequestId,
+    query: CallbackQuery,
+) -> Result<Json<LoginResponse>, (StatusCode, Json<AuthResponse>)> {
+    let oidc = state.oidc.as_ref()
+        .ok_or_else(|| oidc_error(StatusCode::NOT_FOUND, "SSO login is not configured"))?;
+
+    if let Some(error) = query.error {
+        tracing::warn!("Identity provider returned an error: {}", error);
+        return Err(oidc_error(StatusCode::UNAUTHORIZED, "SSO login was not completed"));
+    }
+
+    let (code, login_state) = match (query.code, query.state) {
+        (Some(code), Some(login_state)) => (code, login_state),
+        _ => return Err(oidc_error(StatusCode::BAD_REQUEST, "code and state are required")),
+    };
+
+    let (claims, scopes) = match oidc.exchange(&state.db, &code, &login_state).await {
+        Ok(exchanged) => exchanged,
+        Err(e) => {
+            tracing::warn!("Error while completing the SSO login: {}", e);
+            return Err(oidc_error(StatusCode::UNAUTHORIZED, "SSO login failed"));
+        }
+    };
+
+    // Accounts are linked by email, so only a verified one can be trusted
+    let email = match claims.email {
+        Some(email) if claims.email_verified => email,
+        _ => return Err(oidc_error(StatusCode::FORBIDDEN, "The identity provider did not return a verified email")),
+    };
+
+    let collection: Collection<User> = state.db.collection("user");
+
+    let user = match collection.find_one(doc! { "email": &email }).await {
+        Ok(Some(user_found)) => user_found,
+        Ok(None) => {
+            let username = claims.preferred_username
+                .unwrap_or_else(|| email.split('@').next().unwrap_or(&claims.sub).to_string());
+
+            let mut new_user = User {
+                user_id: None,
+                username,
+                email,
+                password: String::new(),
+                tasks: vec![],
+                notification_preferences: Default::default(),
+            };
+
+            match collection.insert_one(&new_user).await {
+                Ok(user_created) => {
+                    new_user.user_id = user_created.inserted_id.as_object_id();
+                    let changes = vec![
+                        FieldChange { field: "username".to_string(), before: Bson::Null, after: new_user.username.clone().into() },
+                        FieldChange { field: "email".to_string(), before: Bson::Null, after: new_user.email.clone().into() },
+                    ];
+                    record(&state.db, &Actor::user(&new_user, request_id.clone()), AuditAction::UserRegistered, None, changes).await;
+                    new_user
+                }
+                Err(e) => {
+                    tracing::error!("There is some error in provisioning the SSO user: {}", e);
+                    return Err(oidc_error(StatusCode::INTERNAL_SERVER_ERROR, "Internal Server Error"));
+                }
+            }
+        }
+        Err(e) => {
+            tracing::error!("Error while searching for email: {}", e);
+            return Err(oidc_error(StatusCode::INTERNAL_SERVER_ERROR, "Internal Server Error"));
+        }
+    };
+
+    // a login started before the scopes were kept gets every scope
+    let scopes = if scopes.is_empty() { default_scopes() } else { scopes };
+    match issue_token(state, &user, scopes) {
+        Ok(token) => {
+            record(&state.db, &Actor::user(&user, request_id), AuditAction::UserLoggedIn, None, vec![]).await;
+            Ok(Json(LoginResponse {
+                msg: "Login Successful".to_string(),
+                user_id: user.user_id.map(|id| id.to_string()).unwrap_or_default(),
+                token,
+            }))
+        }
+        Err(e) => {
+            tracing::error!("Error while encoding JWT: {}", e);
+            Err(oidc_error(StatusCode::INTERNAL_SERVER_ERROR, "Internal Server Error"))
+        }
+    }
+}
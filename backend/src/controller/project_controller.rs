@@ -0,0 +1,367 @@
+use axum::{
+    extract::{Path, Query, State}, http::StatusCode, Json
+};
+use bson::{doc, oid::ObjectId, Document};
+use futures::TryStreamExt;
+use mongodb::{Collection, Database};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use utoipa::{IntoParams, ToSchema};
+use crate::controller::task_controller::{task_response, TaskResponse};
+use crate::middleware::auth_middleware::Claims;
+use crate::models::{project_model::{Project, Role}, task_model::{not_deleted, Task}};
+use crate::routes::openapi::{ErrorResponse, ObjectIdSchema};
+use crate::utils::authorization::{authorize_project, Action};
+
+#[derive(Deserialize, ToSchema)]
+pub struct ProjectRequest {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+}
+
+// Partial update, absent fields are left as they are
+#[derive(Deserialize, ToSchema)]
+pub struct ProjectPatch {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub archived: Option<bool>,
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct ProjectListQuery {
+    // archived projects are left out unless asked for
+    #[serde(default)]
+    pub archived: bool,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ProjectResponse {
+    #[schema(value_type = ObjectIdSchema)]
+    pub project_id: ObjectId,
+    pub name: String,
+    pub description: String,
+    pub archived: bool,
+    pub owner_id: String,
+    // role of the current user in the project
+    pub role: Role,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ProjectStats {
+    #[schema(value_type = ObjectIdSchema)]
+    pub project_id: ObjectId,
+    pub total: u64,
+    // task count per status, e.g. {"Pending": 3, "Completed": 1}
+    pub by_status: HashMap<String, u64>,
+}
+
+fn project_response(project_id: ObjectId, project: Project, user_id: &str) -> ProjectResponse {
+    ProjectResponse {
+        role: project.role_of(user_id).unwrap_or(Role::Viewer),
+        project_id,
+        name: project.name,
+        description: project.description,
+        archived: project.archived,
+        owner_id: project.owner_id,
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/projects",
+    tag = "projects",
+    security(("bearer" = ["projects:write"])),
+    request_body = ProjectRequest,
+    responses(
+        (status = 200, description = "The created project", body = ProjectResponse),
+        (status = 400, description = "Invalid input", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Missing the projects:write scope", body = ErrorResponse),
+        (status = 429, description = "Too many requests", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    ),
+)]
+pub async fn create_project(
+    State(db): State<Database>,
+    claims: Claims,
+    Json(payload): Json<ProjectRequest>,
+) -> Result<Json<ProjectResponse>, (StatusCode, String)> {
+    if payload.name.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "Project name cannot be empty".to_string()));
+    }
+
+    let collection: Collection<Project> = db.collection("project");
+    let project = Project {
+        project_id: None,
+        name: payload.name,
+        description: payload.description,
+        archived: false,
+        owner_id: claims.user_id.clone(),
+        members: vec![],
+    };
+
+    match collection.insert_one(&project).await {
+        Ok(result) => match result.inserted_id.as_object_id() {
+            Some(project_id) => Ok(Json(project_response(project_id, project, &claims.user_id))),
+            None => Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to get inserted project ID".to_string(),
+            )),
+        },
+        Err(e) => {
+            tracing::error!("Error creating project: {}", e);
+            Err((StatusCode::INTERNAL_SERVER_ERROR, "Internal Server Error".to_string()))
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/projects",
+    tag = "projects",
+    security(("bearer" = ["projects:read"])),
+    params(ProjectListQuery),
+    responses(
+        (status = 200, description = "Projects the current user owns or is a member of", body = Vec<ProjectResponse>),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Missing the projects:read scope", body = ErrorResponse),
+        (status = 429, description = "Too many requests", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    ),
+)]
+pub async fn list_projects(
+    State(db): State<Database>,
+    claims: Claims,
+    Query(query): Query<ProjectListQuery>,
+) -> Result<Json<Vec<ProjectResponse>>, (StatusCode, String)> {
+    let collection: Collection<Project> = db.collection("project");
+    let mut filter = doc! {
+        "$or": [{ "owner_id": &claims.user_id }, { "members.user_id": &claims.user_id }]
+    };
+    if !query.archived {
+        filter.insert("archived", doc! { "$ne": true });
+    }
+
+    let projects: Vec<Project> = match collection.find(filter).sort(doc! { "name": 1 }).await {
+        Ok(cursor) => cursor.try_collect().await,
+        Err(e) => Err(e),
+    }
+    .map_err(|e| {
+        tracing::error!("Error listing projects: {}", e);
+        (StatusCode::INTERNAL_SERVER_ERROR, "Internal Server Error".to_string())
+    })?;
+
+    Ok(Json(projects
+        .into_iter()
+        .filter_map(|project| project.project_id.map(|id| project_response(id, project, &claims.user_id)))
+        .collect()))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/projects/{project_id}",
+    tag = "projects",
+    security(("bearer" = ["projects:read"])),
+    params(("project_id" = String, Path, description = "Hex ObjectId of the project")),
+    responses(
+        (status = 200, description = "The project", body = ProjectResponse),
+        (status = 400, description = "Invalid input", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Missing the projects:read scope, or no right on the project", body = ErrorResponse),
+        (status = 404, description = "Not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    ),
+)]
+pub async fn get_project(
+    State(db): State<Database>,
+    claims: Claims,
+    Path(project_id): Path<String>,
+) -> Result<Json<ProjectResponse>, (StatusCode, String)> {
+    let (obj_id, project) = authorize_project(&db, &project_id, &claims.user_id, Action::View).await?;
+    Ok(Json(project_response(obj_id, project, &claims.user_id)))
+}
+
+#[utoipa::path(
+    patch,
+    path = "/api/v1/projects/{project_id}",
+    tag = "projects",
+    security(("bearer" = ["projects:write"])),
+    params(("project_id" = String, Path, description = "Hex ObjectId of the project")),
+    request_body(content = ProjectPatch, description = "Only the given fields are changed"),
+    responses(
+        (status = 200, description = "The updated project", body = ProjectResponse),
+        (status = 400, description = "Invalid input", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Missing the projects:write scope, or no right on the project", body = ErrorResponse),
+        (status = 404, description = "Not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    ),
+)]
+pub async fn update_project(
+    State(db): State<Database>,
+    claims: Claims,
+    Path(project_id): Path<String>,
+    Json(payload): Json<ProjectPatch>,
+) -> Result<Json<ProjectResponse>, (StatusCode, String)> {
+    let mut set = Document::new();
+    if let Some(name) = payload.name {
+        if name.is_empty() {
+            return Err((StatusCode::BAD_REQUEST, "Project name cannot be empty".to_string()));
+        }
+        set.insert("name", name);
+    }
+    if let Some(description) = payload.description {
+        set.insert("description", description);
+    }
+    if let Some(archived) = payload.archived {
+        set.insert("archived", archived);
+    }
+    if set.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "Nothing to update".to_string()));
+    }
+
+    let (obj_id, _) = authorize_project(&db, &project_id, &claims.user_id, Action::Manage).await?;
+    let collection: Collection<Project> = db.collection("project");
+
+    if let Err(e) = collection.update_one(doc! {"_id": obj_id}, doc! { "$set": set }).await {
+        tracing::error!("Error updating project: {}", e);
+        return Err((StatusCode::INTERNAL_SERVER_ERROR, "Failed to update the project".to_string()));
+    }
+
+    match collection.find_one(doc! {"_id": obj_id}).await {
+        Ok(Some(project)) => Ok(Json(project_response(obj_id, project, &claims.user_id))),
+        _ => Err((StatusCode::INTERNAL_SERVER_ERROR, "Update Not Found".to_string())),
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/projects/{project_id}",
+    tag = "projects",
+    security(("bearer" = ["projects:write"])),
+    params(("project_id" = String, Path, description = "Hex ObjectId of the project")),
+    responses(
+        (status = 204, description = "The project was deleted, its tasks are kept without a project"),
+        (status = 400, description = "Invalid input", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Missing the projects:write scope, or no right on the project", body = ErrorResponse),
+        (status = 404, description = "Not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    ),
+)]
+pub async fn delete_project(
+    State(db): State<Database>,
+    claims: Claims,
+    Path(project_id): Path<String>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let (obj_id, _) = authorize_project(&db, &project_id, &claims.user_id, Action::Manage).await?;
+
+    if let Err(e) = db.collection::<Project>("project").delete_one(doc! {"_id": obj_id}).await {
+        tracing::error!("Error deleting project: {}", e);
+        return Err((StatusCode::INTERNAL_SERVER_ERROR, "Failed to delete the project".to_string()));
+    }
+
+    // the tasks stay with their creators, who are the only ones with access now
+    if let Err(e) = db
+        .collection::<Task>("task")
+        .update_many(
+            doc! { "project_id": obj_id },
+            doc! { "$unset": { "project_id": "" }, "$set": { "assignee_ids": [] } },
+        )
+        .await
+    {
+        tracing::error!("Failed to detach the tasks of the deleted project: {}", e);
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/projects/{project_id}/tasks",
+    tag = "projects",
+    security(("bearer" = ["tasks:read"])),
+    params(("project_id" = String, Path, description = "Hex ObjectId of the project")),
+    responses(
+        (status = 200, description = "Tasks of the project", body = Vec<TaskResponse>),
+        (status = 400, description = "Invalid input", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Missing the tasks:read scope, or no right on the project", body = ErrorResponse),
+        (status = 404, description = "Not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    ),
+)]
+pub async fn project_tasks(
+    State(db): State<Database>,
+    claims: Claims,
+    Path(project_id): Path<String>,
+) -> Result<Json<Vec<TaskResponse>>, (StatusCode, String)> {
+    let (obj_id, _) = authorize_project(&db, &project_id, &claims.user_id, Action::View).await?;
+    let collection: Collection<Task> = db.collection("task");
+
+    let tasks: Vec<Task> = match collection.find(not_deleted(doc! { "project_id": obj_id })).await {
+        Ok(cursor) => cursor.try_collect().await,
+        Err(e) => Err(e),
+    }
+    .map_err(|e| {
+        tracing::error!("Error listing the project tasks: {}", e);
+        (StatusCode::INTERNAL_SERVER_ERROR, "Internal Server Error".to_string())
+    })?;
+
+    Ok(Json(tasks
+        .into_iter()
+        .filter_map(|task| task.task_id.map(|id| task_response(id, task)))
+        .collect()))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/projects/{project_id}/stats",
+    tag = "projects",
+    security(("bearer" = ["projects:read"])),
+    params(("project_id" = String, Path, description = "Hex ObjectId of the project")),
+    responses(
+        (status = 200, description = "Task counts of the project by status", body = ProjectStats),
+        (status = 400, description = "Invalid input", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Missing the projects:read scope, or no right on the project", body = ErrorResponse),
+        (status = 404, description = "Not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    ),
+)]
+pub async fn project_stats(
+    State(db): State<Database>,
+    claims: Claims,
+    Path(project_id): Path<String>,
+) -> Result<Json<ProjectStats>, (StatusCode, String)> {
+    let (obj_id, _) = authorize_project(&db, &project_id, &claims.user_id, Action::View).await?;
+
+    let pipeline = vec![
+        doc! { "$match": not_deleted(doc! { "project_id": obj_id }) },
+        doc! { "$group": { "_id": "$status", "count": { "$sum": 1 } } },
+    ];
+    let groups: Vec<Document> = match db.collection::<Task>("task").aggregate(pipeline).await {
+        Ok(cursor) => cursor.try_collect().await,
+        Err(e) => Err(e),
+    }
+    .map_err(|e| {
+        tracing::error!("Error counting the project tasks: {}", e);
+        (StatusCode::INTERNAL_SERVER_ERROR, "Internal Server Error".to_string())
+    })?;
+
+    let by_status: HashMap<String, u64> = groups
+        .iter()
+        .map(|group| {
+            let status = group.get_str("_id").unwrap_or("unknown").to_string();
+            let count = group.get_i32("count").unwrap_or(0) as u64;
+            (status, count)
+        })
+        .collect();
+
+    Ok(Json(ProjectStats {
+        project_id: obj_id,
+        total: by_status.values().sum(),
+        by_status,
+    }))
+}
@@ -2,38 +2,101 @@ use axum::{
     debug_handler,
     extract::{Path, State}, http::StatusCode, Json
 };
-use bson::{doc, oid::ObjectId};
+use bson::{doc, oid::ObjectId, Bson, DateTime, Document};
+use std::sync::Arc;
+use futures::TryStreamExt;
 use mongodb::{Collection, Database};
 use serde::{Deserialize, Serialize};
-use crate::middleware::auth_middleware::Claims;
-use crate::models::{user_model::User};
+use utoipa::ToSchema;
+use crate::middleware::{auth_middleware::Claims, request_id_middleware::RequestId};
+use crate::models::{
+    audit_model::AuditAction, mention_model::Mention, notification_model::NotificationKind, project_model::Project, task_model::{not_deleted, Task}, user_model::User
+};
+use crate::routes::openapi::{ErrorResponse, ObjectIdSchema};
+use crate::utils::audit::{record_task, Actor};
+use crate::utils::authorization::{
+    assignees_with_access, authorize_project, authorize_task, authorize_trashed_task, projects_allowing, task_allows, Action,
+};
+use crate::utils::mentions::{notify_mentions, resolve_mentions};
+use crate::utils::events::{EventBus, TaskEventKind};
+use crate::utils::notifications::{notify, watchers};
+use crate::utils::webhooks::queue_task_event;
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 pub struct TaskRequest {
     pub taskname: String,
     pub status: String,
+    // Markdown, @usernames of users with access to the task notify them.
+    // Left as it is when absent on update.
+    #[serde(default)]
+    pub description: Option<String>,
+    // RFC 3339, "" removes the due date. Left as it is when absent on update.
+    #[serde(default)]
+    pub due_at: Option<String>,
+    // hex id of one of the user's projects, "" takes the task out of its project
+    #[serde(default)]
+    pub project_id: Option<String>,
 }
 
-#[derive(Serialize)]
+// Partial update, absent fields are left as they are
+#[derive(Deserialize, ToSchema)]
+pub struct TaskPatch {
+    pub taskname: Option<String>,
+    pub status: Option<String>,
+    pub description: Option<String>,
+    // RFC 3339, "" removes the due date
+    pub due_at: Option<String>,
+    // "" takes the task out of its project
+    pub project_id: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
 pub struct TaskResponse {
+    #[schema(value_type = ObjectIdSchema)]
     pub task_id: ObjectId,
     pub taskname: String,
+    pub description: String,
+    // the @usernames of the description that link to a user, the others are plain text
+    pub mentions: Vec<Mention>,
     pub user_id: String,
     pub status: String,
+    #[schema(value_type = Option<ObjectIdSchema>)]
+    pub project_id: Option<ObjectId>,
+    pub created_by: String,
+    pub assignee_ids: Vec<String>,
+    pub comment_count: i64,
+    // RFC 3339
+    pub due_at: Option<String>,
+    // RFC 3339, only set for tasks in the trash
+    pub deleted_at: Option<String>,
+    pub deleted_by: Option<String>,
 }
 
-#[derive(Serialize, Deserialize)]
-pub struct Task {
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub task_id: Option<ObjectId>,
-    pub taskname: String,
-    pub status: String,
+#[derive(Deserialize, ToSchema)]
+pub struct AssignRequest {
     pub user_id: String,
 }
 
+#[utoipa::path(
+    post,
+    path = "/api/v1/tasks",
+    tag = "tasks",
+    security(("bearer" = ["tasks:write"])),
+    request_body(content = TaskRequest, description = "An empty status defaults to Pending"),
+    responses(
+        (status = 200, description = "The created task", body = TaskResponse),
+        (status = 400, description = "Invalid input", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Missing the tasks:write scope, or no edit right on the project", body = ErrorResponse),
+        (status = 429, description = "Too many requests", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    ),
+)]
 pub async fn create_task(
     State(db): State<Database>,
+    State(events): State<Arc<EventBus>>,
     claims: Claims,
+    request_id: RequestId,
     Json(mut payload): Json<TaskRequest>,
 ) -> Result<Json<TaskResponse>, (StatusCode, String)> {
     let user_id = claims.user_id.clone();
@@ -49,15 +112,35 @@ pub async fn create_task(
         payload.status = "Pending".to_string();
     }
 
+    let project_id = match payload.project_id.as_deref() {
+        Some(project_id) if !project_id.is_empty() => Some(task_project(&db, project_id, &user_id).await?.0),
+        _ => None,
+    };
+    let due_at = match payload.due_at.as_deref() {
+        Some(due_at) if !due_at.is_empty() => Some(parse_due_at(due_at)?),
+        _ => None,
+    };
+
     let collection: Collection<Task> = db.collection("task");
     let user_collection = db.collection::<mongodb::bson::Document>("user");
 
-    let new_task = Task {
+    let mut new_task = Task {
         task_id: None,
         taskname: payload.taskname.clone(),
+        description: payload.description.unwrap_or_default(),
+        mentions: vec![],
         status: payload.status.clone(),
         user_id: user_id.clone(),
+        project_id,
+        created_by: claims.user_id.clone(),
+        assignee_ids: vec![],
+        comment_count: 0,
+        due_at,
+        due_notified: false,
+        deleted_at: None,
+        deleted_by: None,
     };
+    new_task.mentions = resolve_mentions(&db, &new_task, &new_task.description).await?;
 
     match collection.insert_one(&new_task).await {
         Ok(insert_result) => {
@@ -81,27 +164,26 @@ pub async fn create_task(
             let update_result = user_collection
                 .update_one(
                     doc! { "_id": user_obj_id },
-                    doc! { "$push": { "tasks": inserted_id.clone() } },
+                    doc! { "$push": { "tasks": inserted_id } },
                 )
                 .await;
 
             if let Err(e) = update_result {
-                eprintln!("Failed to update user with task ID: {}", e);
+                tracing::error!("Failed to update user with task ID: {}", e);
                 return Err((
                     StatusCode::INTERNAL_SERVER_ERROR,
                     "Task created but failed to link with user".to_string(),
                 ));
             }
 
-            return Ok(Json(TaskResponse {
-                task_id: inserted_id,
-                taskname: new_task.taskname,
-                status: new_task.status,
-                user_id: new_task.user_id,
-            }));
+            record_task(&db, &Actor::new(&claims, request_id), AuditAction::TaskCreated, inserted_id, None, Some(&new_task)).await;
+            notify_mentions(&db, &new_task.mentions, &[], &claims, inserted_id, None).await;
+            queue_task_event(&db, TaskEventKind::Created, inserted_id, &new_task).await;
+            events.publish(TaskEventKind::Created, inserted_id, new_task.clone());
+            Ok(Json(task_response(inserted_id, new_task)))
         }
         Err(e) => {
-            eprintln!("Error creating task: {}", e);
+            tracing::error!("Error creating task: {}", e);
             Err((
                 StatusCode::INTERNAL_SERVER_ERROR,
                 "Internal Server Error".to_string(),
@@ -110,9 +192,28 @@ pub async fn create_task(
     }
 }
 
+#[utoipa::path(
+    put,
+    path = "/api/v1/tasks/{task_id}",
+    tag = "tasks",
+    security(("bearer" = ["tasks:write"])),
+    params(("task_id" = String, Path, description = "Hex ObjectId of the task")),
+    request_body = TaskRequest,
+    responses(
+        (status = 200, description = "The updated task", body = TaskResponse),
+        (status = 400, description = "Invalid input", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Missing the tasks:write scope, or no right on the task", body = ErrorResponse),
+        (status = 404, description = "Not found", body = ErrorResponse),
+        (status = 429, description = "Too many requests", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    ),
+)]
 pub async fn update_task(
     State(db): State<Database>,
+    State(events): State<Arc<EventBus>>,
     claims: Claims,
+    request_id: RequestId,
     Path(task_id): Path<String>,
     Json(payload): Json<TaskRequest>,
 ) -> Result<Json<TaskResponse>, (StatusCode, String)> {
@@ -123,76 +224,338 @@ pub async fn update_task(
         ));
     }
 
-    let collection: Collection<Task> = db.collection("task");
-    let user = claims.user_id;
-
-    let obj_id = match ObjectId::parse_str(&task_id) {
-        Ok(id) => id,
-        Err(_) => return Err((StatusCode::BAD_REQUEST, "Invalid Task Id".to_string())),
-    };
+    let (obj_id, mut task) = authorize_task(&db, &task_id, &claims.user_id, Action::Edit).await?;
+    let before = task.clone();
 
-    let update_doc = doc! {
+    let mut update_doc = doc! {
         "$set": {
             "taskname": &payload.taskname,
             "status": &payload.status
         }
     };
-    let filter = doc! {"_id": obj_id};
-
-    match collection.find_one(filter.clone()).await {
-        Ok(Some(task)) => {
-            if task.user_id == user {
-
-                match collection.update_one(filter.clone(), update_doc).await {
-                    Ok(_) => {
-                        match collection.find_one(filter).await{
-                            Ok(Some(updated_task)) => {
-                                Ok(Json(TaskResponse {
-                                task_id: obj_id,
-                                taskname: (&updated_task.taskname).to_string(),
-                                user_id: (&updated_task.user_id).to_string(),
-                                status: (&updated_task.status).to_string(),
-                            }))
-                            }
-                            _ =>
-                                Err((
-                                     StatusCode::INTERNAL_SERVER_ERROR,
-                                    "Update Not Found".to_string()
-                                )),
-                            
-                        }
-                    }
-                    Err(e) => {
-                        println!("Error updating task: {}", e);
-                        return Err((
-                            StatusCode::INTERNAL_SERVER_ERROR,
-                            "Failed to update the task".to_string(),
-                        ));
-                    }
-                }
-            } else {
-                return Err((
-                    StatusCode::UNAUTHORIZED,
-                    "Not authorized to update this task".to_string(),
-                ));
-            }
-        } 
-        Ok(None) => {
-            Err((
-                StatusCode::NOT_FOUND,
-                "The task id is not valid or there is not task with this id".to_string()
-            ))
+    if let Some(project_id) = payload.project_id {
+        set_project(&db, &mut update_doc, &mut task, &project_id, &claims.user_id).await?;
+    }
+    let mentions = match payload.description {
+        Some(description) => Some(set_description(&db, &mut update_doc, &task, description).await?),
+        None => None,
+    };
+    if let Some(due_at) = payload.due_at {
+        set_due_at(&mut update_doc, &due_at)?;
+    }
+
+    let actor = Actor::new(&claims, request_id);
+    let response = save_task(&db, &events, &actor, AuditAction::TaskUpdated, &before, obj_id, update_doc).await?;
+    if let Some(mentions) = mentions {
+        notify_mentions(&db, &mentions, &task.mentions, &claims, obj_id, None).await;
+    }
+    if response.status != task.status {
+        notify(&db, NotificationKind::StatusChanged, watchers(&task), obj_id, None, Some(&claims)).await;
+    }
+    Ok(response)
+}
+
+#[utoipa::path(
+    patch,
+    path = "/api/v1/tasks/{task_id}",
+    tag = "tasks",
+    security(("bearer" = ["tasks:write"])),
+    params(("task_id" = String, Path, description = "Hex ObjectId of the task")),
+    request_body(content = TaskPatch, description = "Only the given fields are changed"),
+    responses(
+        (status = 200, description = "The updated task", body = TaskResponse),
+        (status = 400, description = "Invalid input", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Missing the tasks:write scope, or no right on the task", body = ErrorResponse),
+        (status = 404, description = "Not found", body = ErrorResponse),
+        (status = 429, description = "Too many requests", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    ),
+)]
+pub async fn patch_task(
+    State(db): State<Database>,
+    State(events): State<Arc<EventBus>>,
+    claims: Claims,
+    request_id: RequestId,
+    Path(task_id): Path<String>,
+    Json(payload): Json<TaskPatch>,
+) -> Result<Json<TaskResponse>, (StatusCode, String)> {
+    let mut set = doc! {};
+    if let Some(taskname) = payload.taskname {
+        if taskname.is_empty() {
+            return Err((StatusCode::BAD_REQUEST, "Task name cannot be empty".to_string()));
         }
-        Err(e) => {
-            println!("There is some error in finding the task to update: {}", e);
-            return Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "There is some error in finding the task to update".to_string(),
-            ));
+        set.insert("taskname", taskname);
+    }
+    if let Some(status) = payload.status {
+        if status.is_empty() {
+            return Err((StatusCode::BAD_REQUEST, "Status cannot be empty".to_string()));
         }
+        set.insert("status", status);
+    }
+    if set.is_empty() && payload.project_id.is_none() && payload.description.is_none() && payload.due_at.is_none() {
+        return Err((StatusCode::BAD_REQUEST, "Nothing to update".to_string()));
+    }
+
+    let (obj_id, mut task) = authorize_task(&db, &task_id, &claims.user_id, Action::Edit).await?;
+    let before = task.clone();
+
+    let mut update_doc = doc! {};
+    if !set.is_empty() {
+        update_doc.insert("$set", set);
+    }
+    if let Some(project_id) = payload.project_id {
+        set_project(&db, &mut update_doc, &mut task, &project_id, &claims.user_id).await?;
+    }
+    let mentions = match payload.description {
+        Some(description) => Some(set_description(&db, &mut update_doc, &task, description).await?),
+        None => None,
+    };
+    if let Some(due_at) = payload.due_at {
+        set_due_at(&mut update_doc, &due_at)?;
+    }
+
+    let actor = Actor::new(&claims, request_id);
+    let response = save_task(&db, &events, &actor, AuditAction::TaskUpdated, &before, obj_id, update_doc).await?;
+    if let Some(mentions) = mentions {
+        notify_mentions(&db, &mentions, &task.mentions, &claims, obj_id, None).await;
+    }
+    if response.status != task.status {
+        notify(&db, NotificationKind::StatusChanged, watchers(&task), obj_id, None, Some(&claims)).await;
+    }
+    Ok(response)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/tasks/{task_id}",
+    tag = "tasks",
+    security(("bearer" = ["tasks:read"])),
+    params(("task_id" = String, Path, description = "Hex ObjectId of the task")),
+    responses(
+        (status = 200, description = "The task", body = TaskResponse),
+        (status = 400, description = "Invalid input", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Missing the tasks:read scope, or no right on the task", body = ErrorResponse),
+        (status = 404, description = "Not found", body = ErrorResponse),
+        (status = 429, description = "Too many requests", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    ),
+)]
+pub async fn get_task(
+    State(db): State<Database>,
+    claims: Claims,
+    Path(task_id): Path<String>,
+) -> Result<Json<TaskResponse>, (StatusCode, String)> {
+    let (obj_id, task) = authorize_task(&db, &task_id, &claims.user_id, Action::View).await?;
+    Ok(Json(task_response(obj_id, task)))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/tasks/{task_id}",
+    tag = "tasks",
+    security(("bearer" = ["tasks:write"])),
+    params(("task_id" = String, Path, description = "Hex ObjectId of the task")),
+    responses(
+        (status = 204, description = "The task was moved to the trash"),
+        (status = 400, description = "Invalid input", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Missing the tasks:write scope, or no right on the task", body = ErrorResponse),
+        (status = 404, description = "Not found", body = ErrorResponse),
+        (status = 429, description = "Too many requests", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    ),
+)]
+pub async fn delete_task(
+    State(db): State<Database>,
+    State(events): State<Arc<EventBus>>,
+    claims: Claims,
+    request_id: RequestId,
+    Path(task_id): Path<String>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let collection: Collection<Task> = db.collection("task");
+    let (obj_id, mut task) = authorize_task(&db, &task_id, &claims.user_id, Action::Edit).await?;
+
+    // kept with its comments until the trash sweeper purges it
+    let deleted_at = DateTime::now();
+    if let Err(e) = collection
+        .update_one(doc! { "_id": obj_id }, doc! { "$set": { "deleted_at": deleted_at, "deleted_by": &claims.user_id } })
+        .await
+    {
+        tracing::error!("Error deleting task: {}", e);
+        return Err((StatusCode::INTERNAL_SERVER_ERROR, "Failed to delete the task".to_string()));
+    }
+    let before = task.clone();
+    task.deleted_at = Some(deleted_at);
+    task.deleted_by = Some(claims.user_id.clone());
+
+    // the task is still there, the history shows it going to the trash
+    record_task(&db, &Actor::new(&claims, request_id), AuditAction::TaskDeleted, obj_id, Some(&before), Some(&task)).await;
+    queue_task_event(&db, TaskEventKind::Deleted, obj_id, &task).await;
+    events.publish(TaskEventKind::Deleted, obj_id, task);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub fn task_response(task_id: ObjectId, task: Task) -> TaskResponse {
+    TaskResponse {
+        task_id,
+        taskname: task.taskname,
+        description: task.description,
+        mentions: task.mentions,
+        status: task.status,
+        project_id: task.project_id,
+        // tasks from before created_by was recorded were created by their owner
+        created_by: if task.created_by.is_empty() { task.user_id.clone() } else { task.created_by },
+        user_id: task.user_id,
+        assignee_ids: task.assignee_ids,
+        comment_count: task.comment_count,
+        due_at: task.due_at.and_then(|at| at.try_to_rfc3339_string().ok()),
+        deleted_at: task.deleted_at.and_then(|at| at.try_to_rfc3339_string().ok()),
+        deleted_by: task.deleted_by,
+    }
+}
+
+// A task can only be put in a project the user edits and that is not archived
+async fn task_project(db: &Database, project_id: &str, user_id: &str) -> Result<(ObjectId, Project), (StatusCode, String)> {
+    let (obj_id, project) = authorize_project(db, project_id, user_id, Action::Edit).await?;
+    if project.archived {
+        return Err((StatusCode::BAD_REQUEST, "The project is archived".to_string()));
     }
+    Ok((obj_id, project))
 }
 
+// Adds the project change to an update and applies it to `task`, "" takes the
+// task out of its project. Assignees without access to the new project are
+// unassigned.
+async fn set_project(
+    db: &Database,
+    update: &mut Document,
+    task: &mut Task,
+    project_id: &str,
+    user_id: &str,
+) -> Result<(), (StatusCode, String)> {
+    let project = if project_id.is_empty() {
+        unset_field(update, "project_id");
+        task.project_id = None;
+        None
+    } else {
+        let (obj_id, project) = task_project(db, project_id, user_id).await?;
+        set_field(update, "project_id", obj_id);
+        task.project_id = Some(obj_id);
+        Some(project)
+    };
+
+    let assignee_ids = assignees_with_access(task, project.as_ref());
+    if assignee_ids != task.assignee_ids {
+        set_field(update, "assignee_ids", assignee_ids.clone());
+        task.assignee_ids = assignee_ids;
+    }
+    Ok(())
+}
+
+// Adds the description and its mentions to an update, `task` is the task as it
+// will be after the update so mentions are checked against its new project
+async fn set_description(
+    db: &Database,
+    update: &mut Document,
+    task: &Task,
+    description: String,
+) -> Result<Vec<Mention>, (StatusCode, String)> {
+    let mentions = resolve_mentions(db, task, &description).await?;
+    set_field(update, "mentions", bson::to_bson(&mentions).unwrap_or_default());
+    set_field(update, "description", description);
+    Ok(mentions)
+}
+
+// Adds the due date change to an update, "" removes the due date
+fn set_due_at(update: &mut Document, due_at: &str) -> Result<(), (StatusCode, String)> {
+    if due_at.is_empty() {
+        unset_field(update, "due_at");
+    } else {
+        set_field(update, "due_at", parse_due_at(due_at)?);
+    }
+    // a new due date gets its own reminder
+    set_field(update, "due_notified", false);
+    Ok(())
+}
+
+fn parse_due_at(due_at: &str) -> Result<DateTime, (StatusCode, String)> {
+    DateTime::parse_rfc3339_str(due_at)
+        .map_err(|_| (StatusCode::BAD_REQUEST, "due_at must be an RFC 3339 date and time".to_string()))
+}
+
+fn set_field(update: &mut Document, key: &str, value: impl Into<Bson>) {
+    match update.get_document_mut("$set") {
+        Ok(set) => {
+            set.insert(key, value);
+        }
+        Err(_) => {
+            update.insert("$set", doc! { key: value.into() });
+        }
+    }
+}
+
+fn unset_field(update: &mut Document, key: &str) {
+    match update.get_document_mut("$unset") {
+        Ok(unset) => {
+            unset.insert(key, "");
+        }
+        Err(_) => {
+            update.insert("$unset", doc! { key: "" });
+        }
+    }
+}
+
+// Applies the update, records, queues and publishes it and returns the task as stored
+async fn save_task(
+    db: &Database,
+    events: &EventBus,
+    actor: &Actor,
+    action: AuditAction,
+    before: &Task,
+    task_id: ObjectId,
+    update: Document,
+) -> Result<Json<TaskResponse>, (StatusCode, String)> {
+    let collection: Collection<Task> = db.collection("task");
+    let filter = doc! {"_id": task_id};
+
+    if let Err(e) = collection.update_one(filter.clone(), update).await {
+        tracing::error!("Error updating task: {}", e);
+        return Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to update the task".to_string(),
+        ));
+    }
+
+    match collection.find_one(filter).await {
+        Ok(Some(updated_task)) => {
+            record_task(db, actor, action, task_id, Some(before), Some(&updated_task)).await;
+            queue_task_event(db, TaskEventKind::Updated, task_id, &updated_task).await;
+            events.publish(TaskEventKind::Updated, task_id, updated_task.clone());
+            Ok(Json(task_response(task_id, updated_task)))
+        }
+        _ => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Update Not Found".to_string()
+        )),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/tasks",
+    tag = "tasks",
+    security(("bearer" = ["tasks:read"])),
+    responses(
+        (status = 200, description = "Every task of the current user", body = Vec<TaskResponse>),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Missing the tasks:read scope", body = ErrorResponse),
+        (status = 404, description = "Not found", body = ErrorResponse),
+        (status = 429, description = "Too many requests", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    ),
+)]
 #[debug_handler]
 pub async fn all_for_user(
     State(db): State<Database>,
@@ -219,17 +582,14 @@ pub async fn all_for_user(
             let mut result = Vec::new();
 
             for task_obj_id in user.tasks {
-                let task_filter = doc! { "_id": task_obj_id };
+                let task_filter = not_deleted(doc! { "_id": task_obj_id });
 
                 match task_collection.find_one(task_filter).await {
+                    // a creator removed from the task's project no longer sees it
                     Ok(Some(task_found)) => {
-                        let task_response = TaskResponse {
-                            task_id: task_obj_id,
-                            taskname: task_found.taskname,
-                            status: task_found.status,
-                            user_id: user_id.clone(),
-                        };
-                        result.push(task_response);
+                        if task_allows(&db, &task_found, &user_id, Action::View).await? {
+                            result.push(task_response(task_obj_id, task_found));
+                        }
                     }
                     Ok(None) => continue,
                     Err(e) => {
@@ -257,3 +617,204 @@ pub async fn all_for_user(
         }
     }
 }
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/tasks/assigned",
+    tag = "tasks",
+    security(("bearer" = ["tasks:read"])),
+    responses(
+        (status = 200, description = "Tasks assigned to the current user, across every project", body = Vec<TaskResponse>),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Missing the tasks:read scope", body = ErrorResponse),
+        (status = 429, description = "Too many requests", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    ),
+)]
+pub async fn assigned_to_me(
+    State(db): State<Database>,
+    claims: Claims,
+) -> Result<Json<Vec<TaskResponse>>, (StatusCode, String)> {
+    let collection: Collection<Task> = db.collection("task");
+
+    let tasks: Vec<Task> = match collection.find(not_deleted(doc! { "assignee_ids": &claims.user_id })).await {
+        Ok(cursor) => cursor.try_collect().await,
+        Err(e) => Err(e),
+    }
+    .map_err(|e| {
+        tracing::error!("Error listing the assigned tasks: {}", e);
+        (StatusCode::INTERNAL_SERVER_ERROR, "Internal Server Error".to_string())
+    })?;
+
+    // an assignment left behind doesn't give access to the task
+    let mut result = Vec::new();
+    for task in tasks {
+        if let Some(task_id) = task.task_id
+            && task_allows(&db, &task, &claims.user_id, Action::View).await?
+        {
+            result.push(task_response(task_id, task));
+        }
+    }
+    Ok(Json(result))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/tasks/{task_id}/assignees",
+    tag = "tasks",
+    security(("bearer" = ["tasks:write"])),
+    params(("task_id" = String, Path, description = "Hex ObjectId of the task")),
+    request_body = AssignRequest,
+    responses(
+        (status = 200, description = "The task with the new assignee", body = TaskResponse),
+        (status = 400, description = "Invalid input, or the user has no access to the task", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Missing the tasks:write scope, or no edit right on the task", body = ErrorResponse),
+        (status = 404, description = "Not found", body = ErrorResponse),
+        (status = 429, description = "Too many requests", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    ),
+)]
+pub async fn assign_task(
+    State(db): State<Database>,
+    State(events): State<Arc<EventBus>>,
+    claims: Claims,
+    request_id: RequestId,
+    Path(task_id): Path<String>,
+    Json(payload): Json<AssignRequest>,
+) -> Result<Json<TaskResponse>, (StatusCode, String)> {
+    let (obj_id, task) = authorize_task(&db, &task_id, &claims.user_id, Action::Edit).await?;
+
+    if !task_allows(&db, &task, &payload.user_id, Action::View).await? {
+        return Err((StatusCode::BAD_REQUEST, "The user has no access to this task".to_string()));
+    }
+
+    let update = doc! { "$addToSet": { "assignee_ids": &payload.user_id } };
+    let actor = Actor::new(&claims, request_id);
+    let response = save_task(&db, &events, &actor, AuditAction::TaskAssigned, &task, obj_id, update).await?;
+    if !task.assignee_ids.contains(&payload.user_id) {
+        notify(&db, NotificationKind::Assigned, vec![payload.user_id], obj_id, None, Some(&claims)).await;
+    }
+    Ok(response)
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/tasks/{task_id}/assignees/{user_id}",
+    tag = "tasks",
+    security(("bearer" = ["tasks:write"])),
+    params(
+        ("task_id" = String, Path, description = "Hex ObjectId of the task"),
+        ("user_id" = String, Path, description = "Id of the assignee"),
+    ),
+    responses(
+        (status = 200, description = "The task without the assignee", body = TaskResponse),
+        (status = 400, description = "Invalid input", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Missing the tasks:write scope, or no edit right on the task", body = ErrorResponse),
+        (status = 404, description = "Not found", body = ErrorResponse),
+        (status = 429, description = "Too many requests", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    ),
+)]
+pub async fn unassign_task(
+    State(db): State<Database>,
+    State(events): State<Arc<EventBus>>,
+    claims: Claims,
+    request_id: RequestId,
+    Path((task_id, user_id)): Path<(String, String)>,
+) -> Result<Json<TaskResponse>, (StatusCode, String)> {
+    // assignees can drop a task they can't edit
+    let action = if user_id == claims.user_id { Action::View } else { Action::Edit };
+    let (obj_id, task) = authorize_task(&db, &task_id, &claims.user_id, action).await?;
+
+    let update = doc! { "$pull": { "assignee_ids": &user_id } };
+    let actor = Actor::new(&claims, request_id);
+    save_task(&db, &events, &actor, AuditAction::TaskUnassigned, &task, obj_id, update).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/trash",
+    tag = "tasks",
+    security(("bearer" = ["tasks:read"])),
+    responses(
+        (status = 200, description = "Deleted tasks the current user can restore, most recently deleted first", body = Vec<TaskResponse>),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Missing the tasks:read scope", body = ErrorResponse),
+        (status = 429, description = "Too many requests", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    ),
+)]
+pub async fn list_trash(
+    State(db): State<Database>,
+    claims: Claims,
+) -> Result<Json<Vec<TaskResponse>>, (StatusCode, String)> {
+    let collection: Collection<Task> = db.collection("task");
+    // the tasks restore_task accepts: in a project the user edits, or their own outside any project
+    let projects = projects_allowing(&db, &claims.user_id, Action::Edit).await?;
+    let filter = doc! {
+        "deleted_at": { "$exists": true },
+        "$or": [
+            { "project_id": { "$in": projects } },
+            { "project_id": null, "user_id": &claims.user_id },
+        ],
+    };
+
+    let tasks: Vec<Task> = match collection.find(filter).sort(doc! { "deleted_at": -1 }).await {
+        Ok(cursor) => cursor.try_collect().await,
+        Err(e) => Err(e),
+    }
+    .map_err(|e| {
+        tracing::error!("Error listing the trash: {}", e);
+        (StatusCode::INTERNAL_SERVER_ERROR, "Internal Server Error".to_string())
+    })?;
+
+    Ok(Json(tasks
+        .into_iter()
+        .filter_map(|task| task.task_id.map(|id| task_response(id, task)))
+        .collect()))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/tasks/{task_id}/restore",
+    tag = "tasks",
+    security(("bearer" = ["tasks:write"])),
+    params(("task_id" = String, Path, description = "Hex ObjectId of the task")),
+    responses(
+        (status = 200, description = "The task, out of the trash", body = TaskResponse),
+        (status = 400, description = "Invalid input", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Missing the tasks:write scope, or no edit right on the task", body = ErrorResponse),
+        (status = 404, description = "Not found, or not in the trash", body = ErrorResponse),
+        (status = 429, description = "Too many requests", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    ),
+)]
+pub async fn restore_task(
+    State(db): State<Database>,
+    State(events): State<Arc<EventBus>>,
+    claims: Claims,
+    request_id: RequestId,
+    Path(task_id): Path<String>,
+) -> Result<Json<TaskResponse>, (StatusCode, String)> {
+    let collection: Collection<Task> = db.collection("task");
+    let (obj_id, mut task) = authorize_trashed_task(&db, &task_id, &claims.user_id, Action::Edit).await?;
+
+    if let Err(e) = collection
+        .update_one(doc! { "_id": obj_id }, doc! { "$unset": { "deleted_at": "", "deleted_by": "" } })
+        .await
+    {
+        tracing::error!("Error restoring task: {}", e);
+        return Err((StatusCode::INTERNAL_SERVER_ERROR, "Failed to restore the task".to_string()));
+    }
+    let before = task.clone();
+    task.deleted_at = None;
+    task.deleted_by = None;
+
+    record_task(&db, &Actor::new(&claims, request_id), AuditAction::TaskRestored, obj_id, Some(&before), Some(&task)).await;
+    queue_task_event(&db, TaskEventKind::Restored, obj_id, &task).await;
+    events.publish(TaskEventKind::Restored, obj_id, task.clone());
+    Ok(Json(task_response(obj_id, task)))
+}
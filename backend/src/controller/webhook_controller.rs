@@ -0,0 +1,490 @@
+use axum::{
+    extract::{Path, Query, State}, http::StatusCode, Json
+};
+use bson::{doc, oid::ObjectId, DateTime, Document};
+use futures::TryStreamExt;
+use mongodb::{Collection, Database};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use utoipa::{IntoParams, ToSchema};
+use crate::middleware::auth_middleware::Claims;
+use crate::models::webhook_model::{DeliveryAttempt, Webhook, WebhookDelivery};
+use crate::routes::openapi::{ErrorResponse, ObjectIdSchema};
+use crate::utils::{
+    config::WebhooksConfig,
+    state::AppState,
+    webhooks::{check_destination, enqueue, generate_secret, EVENTS},
+};
+
+const DEFAULT_PAGE_SIZE: i64 = 50;
+const MAX_PAGE_SIZE: i64 = 100;
+
+#[derive(Deserialize, ToSchema)]
+pub struct WebhookRequest {
+    // https:// URL the events are POSTed to
+    pub url: String,
+    // "task.created", "task.updated", "task.deleted" or "task.restored", empty for every event
+    #[serde(default)]
+    pub events: Vec<String>,
+}
+
+// Partial update, absent fields are left as they are
+#[derive(Deserialize, ToSchema)]
+pub struct WebhookPatch {
+    pub url: Option<String>,
+    pub events: Option<Vec<String>>,
+    // true turns a disabled webhook back on
+    pub active: Option<bool>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct WebhookResponse {
+    #[schema(value_type = ObjectIdSchema)]
+    pub webhook_id: ObjectId,
+    pub url: String,
+    pub events: Vec<String>,
+    pub active: bool,
+    pub consecutive_failures: i32,
+    // RFC 3339
+    pub created_at: String,
+    pub disabled_at: Option<String>,
+    // only returned once, when the webhook is created
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct DeliveryListQuery {
+    // next_cursor of the previous page
+    pub after: Option<String>,
+    // 1 to 100, 50 by default
+    pub limit: Option<i64>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct AttemptResponse {
+    // RFC 3339
+    pub at: String,
+    // null when no response came back
+    pub status_code: Option<u16>,
+    pub latency_ms: i64,
+    // start of the response body
+    pub response_snippet: String,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct DeliveryResponse {
+    #[schema(value_type = ObjectIdSchema)]
+    pub delivery_id: ObjectId,
+    pub event: String,
+    // "pending", "succeeded", "failed" or "cancelled"
+    pub status: String,
+    pub attempts: Vec<AttemptResponse>,
+    // RFC 3339, only while pending
+    pub next_attempt_at: Option<String>,
+    pub created_at: String,
+}
+
+// Deliveries newest first, pass next_cursor as `after` to get the next page
+#[derive(Serialize, ToSchema)]
+pub struct DeliveryPage {
+    pub deliveries: Vec<DeliveryResponse>,
+    pub next_cursor: Option<String>,
+}
+
+fn rfc3339(at: DateTime) -> String {
+    at.try_to_rfc3339_string().unwrap_or_default()
+}
+
+fn webhook_response(webhook_id: ObjectId, webhook: Webhook) -> WebhookResponse {
+    WebhookResponse {
+        webhook_id,
+        url: webhook.url,
+        events: webhook.events,
+        active: webhook.active,
+        consecutive_failures: webhook.consecutive_failures,
+        created_at: rfc3339(webhook.created_at),
+        disabled_at: webhook.disabled_at.map(rfc3339),
+        secret: None,
+    }
+}
+
+fn attempt_response(attempt: DeliveryAttempt) -> AttemptResponse {
+    AttemptResponse {
+        at: rfc3339(attempt.at),
+        status_code: attempt.status_code,
+        latency_ms: attempt.latency_ms,
+        response_snippet: attempt.response_snippet,
+        error: attempt.error,
+    }
+}
+
+fn delivery_response(delivery: WebhookDelivery) -> DeliveryResponse {
+    let pending = delivery.status == "pending";
+    DeliveryResponse {
+        delivery_id: delivery.delivery_id,
+        event: delivery.event,
+        status: delivery.status,
+        attempts: delivery.attempts.into_iter().map(attempt_response).collect(),
+        next_attempt_at: pending.then(|| rfc3339(delivery.next_attempt_at)),
+        created_at: rfc3339(delivery.created_at),
+    }
+}
+
+fn internal_error(context: &str, e: mongodb::error::Error) -> (StatusCode, String) {
+    tracing::error!("{}: {}", context, e);
+    (StatusCode::INTERNAL_SERVER_ERROR, "Internal Server Error".to_string())
+}
+
+async fn check_url(url: &str, config: &WebhooksConfig) -> Result<(), (StatusCode, String)> {
+    let parsed = Url::parse(url).map_err(|_| (StatusCode::BAD_REQUEST, "Invalid webhook URL".to_string()))?;
+    let allowed = match parsed.scheme() {
+        "https" => true,
+        "http" => config.allow_http,
+        _ => false,
+    };
+    if !allowed || parsed.host_str().is_none() {
+        let schemes = if config.allow_http { "http:// or https://" } else { "https://" };
+        return Err((StatusCode::BAD_REQUEST, format!("The webhook URL must be an {} URL", schemes)));
+    }
+    check_destination(&parsed, config)
+        .await
+        .map_err(|e| (StatusCode::BAD_REQUEST, format!("The webhook URL is not allowed: {}", e)))
+}
+
+fn check_events(events: &[String]) -> Result<(), (StatusCode, String)> {
+    match events.iter().find(|event| !EVENTS.contains(&event.as_str())) {
+        Some(event) => Err((
+            StatusCode::BAD_REQUEST,
+            format!("Unknown event {}, expected one of {}", event, EVENTS.join(", ")),
+        )),
+        None => Ok(()),
+    }
+}
+
+// Loads a webhook of the user, someone else's looks the same as a missing one
+async fn own_webhook(db: &Database, webhook_id: &str, user_id: &str) -> Result<(ObjectId, Webhook), (StatusCode, String)> {
+    let obj_id = ObjectId::parse_str(webhook_id)
+        .map_err(|_| (StatusCode::BAD_REQUEST, "Invalid Webhook Id".to_string()))?;
+
+    let webhook = db
+        .collection::<Webhook>("webhook")
+        .find_one(doc! { "_id": obj_id, "user_id": user_id })
+        .await
+        .map_err(|e| internal_error("Error while finding the webhook", e))?
+        .ok_or_else(|| (StatusCode::NOT_FOUND, "There is no webhook with this id".to_string()))?;
+    Ok((obj_id, webhook))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/webhooks",
+    tag = "webhooks",
+    security(("bearer" = ["webhooks:read"])),
+    responses(
+        (status = 200, description = "The current user's webhooks", body = Vec<WebhookResponse>),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Missing the webhooks:read scope", body = ErrorResponse),
+        (status = 429, description = "Too many requests", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    ),
+)]
+pub async fn list_webhooks(
+    State(db): State<Database>,
+    claims: Claims,
+) -> Result<Json<Vec<WebhookResponse>>, (StatusCode, String)> {
+    let collection: Collection<Webhook> = db.collection("webhook");
+
+    let webhooks: Vec<Webhook> = match collection.find(doc! { "user_id": &claims.user_id }).await {
+        Ok(cursor) => cursor.try_collect().await,
+        Err(e) => Err(e),
+    }
+    .map_err(|e| internal_error("Error while listing the webhooks", e))?;
+
+    Ok(Json(webhooks
+        .into_iter()
+        .filter_map(|webhook| webhook.webhook_id.map(|id| webhook_response(id, webhook)))
+        .collect()))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/webhooks",
+    tag = "webhooks",
+    security(("bearer" = ["webhooks:write"])),
+    request_body = WebhookRequest,
+    responses(
+        (status = 200, description = "The webhook, with the signing secret shown only this once", body = WebhookResponse),
+        (status = 400, description = "Invalid URL, a URL to a private address or unknown event", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Missing the webhooks:write scope", body = ErrorResponse),
+        (status = 429, description = "Too many requests", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    ),
+)]
+pub async fn create_webhook(
+    State(state): State<AppState>,
+    claims: Claims,
+    Json(payload): Json<WebhookRequest>,
+) -> Result<Json<WebhookResponse>, (StatusCode, String)> {
+    check_url(&payload.url, &state.config.webhooks).await?;
+    check_events(&payload.events)?;
+
+    let secret = generate_secret().map_err(|e| {
+        tracing::error!("{}", e);
+        (StatusCode::INTERNAL_SERVER_ERROR, "Internal Server Error".to_string())
+    })?;
+    let webhook = Webhook {
+        webhook_id: None,
+        user_id: claims.user_id.clone(),
+        url: payload.url,
+        secret: secret.clone(),
+        events: payload.events,
+        active: true,
+        consecutive_failures: 0,
+        created_at: DateTime::now(),
+        disabled_at: None,
+    };
+
+    let result = state
+        .db
+        .collection::<Webhook>("webhook")
+        .insert_one(&webhook)
+        .await
+        .map_err(|e| internal_error("Error while saving the webhook", e))?;
+    let webhook_id = result.inserted_id.as_object_id().ok_or_else(|| {
+        (StatusCode::INTERNAL_SERVER_ERROR, "Failed to get inserted webhook ID".to_string())
+    })?;
+
+    let mut response = webhook_response(webhook_id, webhook);
+    response.secret = Some(secret);
+    Ok(Json(response))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/webhooks/{webhook_id}",
+    tag = "webhooks",
+    security(("bearer" = ["webhooks:read"])),
+    params(("webhook_id" = String, Path, description = "Hex ObjectId of the webhook")),
+    responses(
+        (status = 200, description = "The webhook", body = WebhookResponse),
+        (status = 400, description = "Invalid input", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Missing the webhooks:read scope", body = ErrorResponse),
+        (status = 404, description = "Not found", body = ErrorResponse),
+        (status = 429, description = "Too many requests", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    ),
+)]
+pub async fn get_webhook(
+    State(db): State<Database>,
+    claims: Claims,
+    Path(webhook_id): Path<String>,
+) -> Result<Json<WebhookResponse>, (StatusCode, String)> {
+    let (obj_id, webhook) = own_webhook(&db, &webhook_id, &claims.user_id).await?;
+    Ok(Json(webhook_response(obj_id, webhook)))
+}
+
+#[utoipa::path(
+    patch,
+    path = "/api/v1/webhooks/{webhook_id}",
+    tag = "webhooks",
+    security(("bearer" = ["webhooks:write"])),
+    params(("webhook_id" = String, Path, description = "Hex ObjectId of the webhook")),
+    request_body(content = WebhookPatch, description = "Only the given fields are changed"),
+    responses(
+        (status = 200, description = "The updated webhook", body = WebhookResponse),
+        (status = 400, description = "Invalid URL, a URL to a private address, unknown event or nothing to update", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Missing the webhooks:write scope", body = ErrorResponse),
+        (status = 404, description = "Not found", body = ErrorResponse),
+        (status = 429, description = "Too many requests", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    ),
+)]
+pub async fn update_webhook(
+    State(state): State<AppState>,
+    claims: Claims,
+    Path(webhook_id): Path<String>,
+    Json(payload): Json<WebhookPatch>,
+) -> Result<Json<WebhookResponse>, (StatusCode, String)> {
+    let (obj_id, mut webhook) = own_webhook(&state.db, &webhook_id, &claims.user_id).await?;
+
+    let mut set = Document::new();
+    let mut unset = Document::new();
+    if let Some(url) = payload.url {
+        check_url(&url, &state.config.webhooks).await?;
+        set.insert("url", &url);
+        webhook.url = url;
+    }
+    if let Some(events) = payload.events {
+        check_events(&events)?;
+        set.insert("events", &events);
+        webhook.events = events;
+    }
+    if let Some(active) = payload.active {
+        set.insert("active", active);
+        if active {
+            // turned back on with a clean slate
+            set.insert("consecutive_failures", 0);
+            unset.insert("disabled_at", "");
+            webhook.consecutive_failures = 0;
+            webhook.disabled_at = None;
+        } else if webhook.active {
+            let now = DateTime::now();
+            set.insert("disabled_at", now);
+            webhook.disabled_at = Some(now);
+        }
+        webhook.active = active;
+    }
+    if set.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "Nothing to update".to_string()));
+    }
+
+    let mut update = doc! { "$set": set };
+    if !unset.is_empty() {
+        update.insert("$unset", unset);
+    }
+    state
+        .db
+        .collection::<Webhook>("webhook")
+        .update_one(doc! { "_id": obj_id }, update)
+        .await
+        .map_err(|e| internal_error("Error while updating the webhook", e))?;
+
+    Ok(Json(webhook_response(obj_id, webhook)))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/webhooks/{webhook_id}",
+    tag = "webhooks",
+    security(("bearer" = ["webhooks:write"])),
+    params(("webhook_id" = String, Path, description = "Hex ObjectId of the webhook")),
+    responses(
+        (status = 204, description = "The webhook and its delivery log were deleted"),
+        (status = 400, description = "Invalid input", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Missing the webhooks:write scope", body = ErrorResponse),
+        (status = 404, description = "Not found", body = ErrorResponse),
+        (status = 429, description = "Too many requests", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    ),
+)]
+pub async fn delete_webhook(
+    State(db): State<Database>,
+    claims: Claims,
+    Path(webhook_id): Path<String>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let (obj_id, _) = own_webhook(&db, &webhook_id, &claims.user_id).await?;
+
+    db.collection::<Webhook>("webhook")
+        .delete_one(doc! { "_id": obj_id })
+        .await
+        .map_err(|e| internal_error("Error while deleting the webhook", e))?;
+
+    // the worker cancels the ones it already claimed
+    if let Err(e) = db
+        .collection::<WebhookDelivery>("webhook_delivery")
+        .delete_many(doc! { "webhook_id": obj_id })
+        .await
+    {
+        tracing::error!("Failed to delete the deliveries of the deleted webhook: {}", e);
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/webhooks/{webhook_id}/ping",
+    tag = "webhooks",
+    security(("bearer" = ["webhooks:write"])),
+    params(("webhook_id" = String, Path, description = "Hex ObjectId of the webhook")),
+    responses(
+        (status = 202, description = "A ping event was queued, its delivery shows up in the log"),
+        (status = 400, description = "Invalid input, or the webhook is disabled", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Missing the webhooks:write scope", body = ErrorResponse),
+        (status = 404, description = "Not found", body = ErrorResponse),
+        (status = 429, description = "Too many requests", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    ),
+)]
+pub async fn ping_webhook(
+    State(db): State<Database>,
+    claims: Claims,
+    Path(webhook_id): Path<String>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let (obj_id, webhook) = own_webhook(&db, &webhook_id, &claims.user_id).await?;
+    if !webhook.active {
+        return Err((StatusCode::BAD_REQUEST, "The webhook is disabled".to_string()));
+    }
+
+    enqueue(&db, obj_id, None, "ping", json!({ "webhook_id": obj_id.to_hex() }))
+        .await
+        .map_err(|e| internal_error("Error while queueing the ping", e))?;
+    Ok(StatusCode::ACCEPTED)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/webhooks/{webhook_id}/deliveries",
+    tag = "webhooks",
+    security(("bearer" = ["webhooks:read"])),
+    params(
+        ("webhook_id" = String, Path, description = "Hex ObjectId of the webhook"),
+        DeliveryListQuery,
+    ),
+    responses(
+        (status = 200, description = "A page of the webhook's deliveries with every attempt", body = DeliveryPage),
+        (status = 400, description = "Invalid input", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Missing the webhooks:read scope", body = ErrorResponse),
+        (status = 404, description = "Not found", body = ErrorResponse),
+        (status = 429, description = "Too many requests", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    ),
+)]
+pub async fn list_deliveries(
+    State(db): State<Database>,
+    claims: Claims,
+    Path(webhook_id): Path<String>,
+    Query(query): Query<DeliveryListQuery>,
+) -> Result<Json<DeliveryPage>, (StatusCode, String)> {
+    let (obj_id, _) = own_webhook(&db, &webhook_id, &claims.user_id).await?;
+
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+    if !(1..=MAX_PAGE_SIZE).contains(&limit) {
+        return Err((StatusCode::BAD_REQUEST, format!("limit must be between 1 and {}", MAX_PAGE_SIZE)));
+    }
+
+    let mut filter = doc! { "webhook_id": obj_id };
+    if let Some(after) = query.after.as_deref() {
+        let after = ObjectId::parse_str(after)
+            .map_err(|_| (StatusCode::BAD_REQUEST, "Invalid cursor".to_string()))?;
+        filter.insert("_id", doc! { "$lt": after });
+    }
+
+    let collection: Collection<WebhookDelivery> = db.collection("webhook_delivery");
+    // one more than asked tells whether there is a next page
+    let mut deliveries: Vec<WebhookDelivery> = match collection.find(filter).sort(doc! { "_id": -1 }).limit(limit + 1).await {
+        Ok(cursor) => cursor.try_collect().await,
+        Err(e) => Err(e),
+    }
+    .map_err(|e| internal_error("Error while listing the deliveries", e))?;
+
+    let next_cursor = if deliveries.len() as i64 > limit {
+        deliveries.truncate(limit as usize);
+        deliveries.last().map(|delivery| delivery.delivery_id.to_hex())
+    } else {
+        None
+    };
+
+    Ok(Json(DeliveryPage {
+        deliveries: deliveries.into_iter().map(delivery_response).collect(),
+        next_cursor,
+    }))
+}
@@ -1,8 +1,6 @@
-use axum;
 use mongodb::Database;
-use std::env;
 use dotenvy::dotenv;
-use std::net::SocketAddr;
+use std::sync::{atomic::AtomicBool, Arc};
 
 mod controller;
 mod models;
@@ -10,19 +8,116 @@ mod utils;
 mod routes;
 mod middleware;
 
-use utils::db::db_connect;
-use routes::router::create_router;
+use utils::db::{db_connect, migrate_task_user_ids};
+use routes::router::{create_admin_router, create_router};
+use utils::audit::audit_sweeper;
+use utils::config::Config;
+use utils::events::EventBus;
+use utils::keyring::Keyring;
+use utils::logging::init_logging;
+use utils::metrics::init_metrics;
+use utils::notifications::due_soon_sweeper;
+use utils::oidc::{self, OidcClient};
+use utils::password::Passwords;
+use utils::rate_limit::RateLimiter;
+use utils::server::run_server;
+use utils::state::AppState;
+use utils::trash::trash_sweeper;
+use utils::webhooks::delivery_worker;
+
+// Configuration problems end the process with a readable message instead of a panic
+fn exit_on_error<T>(result: Result<T, String>, context: &str) -> T {
+    result.unwrap_or_else(|e| {
+        eprintln!("{}: {}", context, e);
+        std::process::exit(1);
+    })
+}
 
 #[tokio::main]
 async fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("openapi") {
+        exit_on_error(routes::openapi::run_command(&args[1..]), "OpenAPI");
+        return;
+    }
+
     dotenv().ok();
-    let db: Database = db_connect().await;
-    let port: u16 = env::var("PORT").expect("Port is not set").parse().expect("Must be a number");
-    
-    let addr= SocketAddr::from(([127, 0, 0, 1], port));
-    let app = create_router(db).await;
-    println!("The server is up on address: {}", addr);
-    println!("Mongo DB is connected Successfully!!!");
-    let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
-    axum::serve(listener, app).await.unwrap();
+    let config = exit_on_error(Config::load(), "Invalid configuration");
+    init_logging(&config.logging);
+    // rustls is shared by the TLS listener, reqwest and the MongoDB driver
+    let _ = rustls::crypto::ring::default_provider().install_default();
+
+    let metrics = if config.metrics.enabled {
+        Some(exit_on_error(init_metrics(), "Failed to set up metrics"))
+    } else {
+        None
+    };
+
+    let db: Database = exit_on_error(db_connect(&config.database).await, "Failed to connect to MongoDB");
+    exit_on_error(migrate_task_user_ids(&db).await, "Failed to migrate the stored tasks");
+    let keyring = exit_on_error(Keyring::from_config(&config.auth), "Failed to load the JWT signing keys");
+    let passwords = exit_on_error(Passwords::from_config(&config.passwords), "Invalid password hashing configuration");
+    if config.oidc.is_some() {
+        exit_on_error(oidc::create_indexes(&db).await, "Failed to create the SSO login index");
+    }
+    let oidc = config.oidc.as_ref().map(|oidc| Arc::new(OidcClient::new(oidc)));
+
+    let rate_limiter = RateLimiter::new(config.rate_limit.clone());
+    let events = EventBus::new(config.events.history_size);
+
+    let server_config = config.server.clone();
+    let tls_config = config.tls.clone();
+    let metrics_addr = config.metrics_addr();
+    let client = db.client().clone();
+    let draining = Arc::new(AtomicBool::new(false));
+    let state = AppState {
+        config: Arc::new(config),
+        db,
+        keyring: Arc::new(keyring),
+        oidc,
+        passwords: Arc::new(passwords),
+        metrics,
+        rate_limiter: Arc::new(rate_limiter),
+        events: Arc::new(events),
+        draining: draining.clone(),
+    };
+
+    if let Some(metrics_addr) = metrics_addr {
+        let admin = create_admin_router(state.clone());
+        let listener = exit_on_error(
+            tokio::net::TcpListener::bind(metrics_addr).await.map_err(|e| format!("{}: {}", metrics_addr, e)),
+            "Failed to bind the metrics address",
+        );
+        tracing::info!("Metrics are served on address: {}", metrics_addr);
+        tokio::spawn(async move { axum::serve(listener, admin).await });
+    }
+
+    if state.config.webhooks.poll_interval_secs > 0 {
+        tokio::spawn(delivery_worker(state.db.clone(), state.config.webhooks.clone()));
+    }
+    if state.config.notifications.due_sweep_interval_secs > 0 {
+        tokio::spawn(due_soon_sweeper(state.db.clone(), state.config.notifications.clone()));
+    }
+    if state.config.audit.retention_days > 0 {
+        tokio::spawn(audit_sweeper(state.db.clone(), state.config.audit.clone()));
+    }
+    if state.config.trash.retention_days > 0 {
+        tokio::spawn(trash_sweeper(state.db.clone(), state.config.trash.clone()));
+    }
+
+    let app = create_router(state).await;
+    tracing::info!("Mongo DB is connected Successfully!!!");
+
+    let drained = match run_server(&server_config, tls_config.as_ref(), app, draining).await {
+        Ok(drained) => drained,
+        Err(e) => {
+            tracing::error!("Server error: {}", e);
+            client.shutdown().immediate(true).await;
+            std::process::exit(1);
+        }
+    };
+
+    // cursors of cut requests may still be open, so only wait for them after a clean drain
+    client.shutdown().immediate(!drained).await;
+    tracing::info!("Shutdown complete");
 }
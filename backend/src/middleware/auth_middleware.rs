@@ -1,20 +1,24 @@
 use axum::{
-    body::Body, extract::FromRequestParts, http::{Request, StatusCode}, middleware::Next, response::Response, Json
+    body::Body, extract::{FromRequestParts, State}, http::{Request, StatusCode}, middleware::Next, response::Response, Json
 };
 use serde::{Serialize, Deserialize};
 use serde_json::json;
-use jsonwebtoken::{decode, DecodingKey, Validation};
-use std::env;
+use std::sync::Arc;
+
+use crate::utils::keyring::Keyring;
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Claims {
     pub user_id: String,
     pub username: String,
+    #[serde(default)]
+    pub scopes: Vec<String>,
     pub exp: usize,
     pub iat: usize,
 }
 
 pub async fn auth_middleware (
+    State(keyring): State<Arc<Keyring>>,
     mut request:Request<Body>,
     next: Next,
 ) -> Result<Response, (StatusCode, Json<serde_json::Value>)> {
@@ -41,20 +45,17 @@ pub async fn auth_middleware (
     };
 
 
-    match decode::<Claims> (
-        token,
-        &DecodingKey::from_secret(env::var("JWT_SECRET").expect("JWT_SECRET not set").as_ref()),
-        &Validation::default()
-    ) {
+    match keyring.verify::<Claims>(token) {
         Ok(token_data) => {
+            tracing::Span::current().record("user_id", token_data.claims.user_id.as_str());
             request.extensions_mut().insert(token_data.claims);
             Ok(next.run(request).await)
         } Err(e) => {
-            println!("JWT decode error: {}", e);
-            return Err((
+            tracing::debug!("Rejected token: {}", e);
+            Err((
                 StatusCode::UNAUTHORIZED,
                 Json(json!({"error": "Invalid token"}))
-            ));
+            ))
         }
     }
 }
@@ -0,0 +1,41 @@
+use axum::{
+    body::Body, extract::{RawPathParams, State}, http::{header, HeaderValue, Request}, middleware::Next, response::Response
+};
+
+// When the unversioned routes were deprecated (RFC 9745, 2026-10-19) and when they go away (RFC 8594)
+const DEPRECATED_AT: &str = "@1792368000";
+const SUNSET_AT: &str = "Fri, 30 Apr 2027 00:00:00 GMT";
+
+// The successor route with the {placeholders} filled in from the path of the request
+fn successor_url(successor: &str, params: &RawPathParams) -> String {
+    params.iter().fold(successor.to_string(), |url, (key, value)| {
+        let encoded: String = value
+            .bytes()
+            .map(|byte| match byte {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (byte as char).to_string(),
+                _ => format!("%{:02X}", byte),
+            })
+            .collect();
+        url.replace(&format!("{{{}}}", key), &encoded)
+    })
+}
+
+// Marks a legacy route as deprecated and links the /api/v1 route replacing it,
+// the successor uses the same {placeholders} as the legacy route
+// Usage: .layer(middleware::from_fn_with_state("/api/v1/tasks/{task_id}", deprecated))
+pub async fn deprecated(
+    State(successor): State<&'static str>,
+    params: RawPathParams,
+    request: Request<Body>,
+    next: Next,
+) -> Response {
+    let successor = successor_url(successor, &params);
+    let mut response = next.run(request).await;
+    let headers = response.headers_mut();
+    headers.insert("Deprecation", HeaderValue::from_static(DEPRECATED_AT));
+    headers.insert("Sunset", HeaderValue::from_static(SUNSET_AT));
+    if let Ok(link) = HeaderValue::from_str(&format!("<{}>; rel=\"successor-version\"", successor)) {
+        headers.insert(header::LINK, link);
+    }
+    response
+}
@@ -0,0 +1,26 @@
+use axum::{body::Body, extract::MatchedPath, http::Request, middleware::Next, response::Response};
+use std::time::Instant;
+
+// Request count and latency by route and status
+pub async fn track_metrics(request: Request<Body>, next: Next) -> Response {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| "unmatched".to_string());
+    let method = request.method().to_string();
+
+    let start = Instant::now();
+    let response = next.run(request).await;
+    let latency = start.elapsed().as_secs_f64();
+
+    let labels = [
+        ("method", method),
+        ("route", route),
+        ("status", response.status().as_u16().to_string()),
+    ];
+    metrics::counter!("http_requests_total", &labels).increment(1);
+    metrics::histogram!("http_request_duration_seconds", &labels).record(latency);
+
+    response
+}
@@ -1 +1,7 @@
-pub mod auth_middleware;
\ No newline at end of file
+pub mod auth_middleware;
+pub mod deprecation_middleware;
+pub mod metrics_middleware;
+pub mod rate_limit_middleware;
+pub mod request_id_middleware;
+pub mod scope_middleware;
+pub mod security_middleware;
\ No newline at end of file
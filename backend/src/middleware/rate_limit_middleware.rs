@@ -0,0 +1,105 @@
+use axum::{
+    body::Body, extract::{ConnectInfo, MatchedPath, State}, http::{HeaderMap, HeaderValue, Request, StatusCode}, middleware::Next, response::{IntoResponse, Response}, Json
+};
+use serde_json::json;
+use std::{net::SocketAddr, sync::Arc};
+
+use crate::middleware::auth_middleware::Claims;
+use crate::utils::rate_limit::{Decision, RateLimiter};
+
+fn set_headers(headers: &mut HeaderMap, decision: &Decision) {
+    headers.insert("RateLimit-Limit", HeaderValue::from(decision.limit));
+    headers.insert("RateLimit-Remaining", HeaderValue::from(decision.remaining));
+    headers.insert("RateLimit-Reset", HeaderValue::from(decision.reset_secs));
+}
+
+// Authenticated requests are limited per user, anonymous ones (register, login) per client IP
+fn subject(request: &Request<Body>, trust_forwarded_for: bool) -> String {
+    if let Some(claims) = request.extensions().get::<Claims>() {
+        return format!("user:{}", claims.user_id);
+    }
+
+    // proxies append to X-Forwarded-For, so only the last entry, the address
+    // the trusted proxy saw, is not up to the client
+    if trust_forwarded_for
+        && let Some(ip) = request
+            .headers()
+            .get_all("x-forwarded-for")
+            .iter()
+            .next_back()
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.rsplit(',').next())
+            .filter(|ip| !ip.trim().is_empty())
+    {
+        return format!("ip:{}", ip.trim());
+    }
+
+    match request.extensions().get::<ConnectInfo<SocketAddr>>() {
+        Some(ConnectInfo(addr)) => format!("ip:{}", addr.ip()),
+        // e.g. behind a proxy on the unix socket without X-Forwarded-For
+        None => "ip:unknown".to_string(),
+    }
+}
+
+// Token bucket per method, route and subject, has to run after auth_middleware
+pub async fn rate_limit(
+    State(limiter): State<Arc<RateLimiter>>,
+    request: Request<Body>,
+    next: Next,
+) -> Response {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_default();
+    let subject = subject(&request, limiter.trust_forwarded_for());
+
+    let decision = match limiter.check(request.method().as_str(), &route, &subject).await {
+        Some(decision) => decision,
+        None => return next.run(request).await,
+    };
+
+    if !decision.allowed {
+        let mut response = (
+            StatusCode::TOO_MANY_REQUESTS,
+            Json(json!({"error": "Too many requests", "retry_after": decision.retry_after_secs})),
+        ).into_response();
+        set_headers(response.headers_mut(), &decision);
+        response.headers_mut().insert("Retry-After", HeaderValue::from(decision.retry_after_secs));
+        return response;
+    }
+
+    let mut response = next.run(request).await;
+    set_headers(response.headers_mut(), &decision);
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn forwarded_request(forwarded_for: &[&str]) -> Request<Body> {
+        let mut request = Request::builder().uri("/user/login");
+        for value in forwarded_for {
+            request = request.header("x-forwarded-for", *value);
+        }
+        let mut request = request.body(Body::empty()).unwrap();
+        request.extensions_mut().insert(ConnectInfo(SocketAddr::from(([10, 0, 0, 2], 44000))));
+        request
+    }
+
+    #[test]
+    fn forged_forwarded_entries_do_not_change_the_key() {
+        let honest = subject(&forwarded_request(&["203.0.113.7"]), true);
+        assert_eq!(honest, "ip:203.0.113.7");
+        assert_eq!(subject(&forwarded_request(&["198.51.100.1, 203.0.113.7"]), true), honest);
+        assert_eq!(subject(&forwarded_request(&["198.51.100.2,203.0.113.7"]), true), honest);
+        assert_eq!(subject(&forwarded_request(&["198.51.100.3", "203.0.113.7"]), true), honest);
+    }
+
+    #[test]
+    fn forwarded_for_is_ignored_unless_trusted() {
+        assert_eq!(subject(&forwarded_request(&["203.0.113.7"]), false), "ip:10.0.0.2");
+        assert_eq!(subject(&forwarded_request(&[]), true), "ip:10.0.0.2");
+    }
+}
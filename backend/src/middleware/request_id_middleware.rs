@@ -0,0 +1,80 @@
+use axum::{
+    body::{to_bytes, Body}, extract::FromRequestParts, http::{header, request::Parts, HeaderValue, Request}, middleware::Next, response::Response
+};
+use std::convert::Infallible;
+
+use crate::routes::openapi::ErrorResponse;
+
+// Error bodies bigger than this are passed through untouched
+const MAX_ERROR_BODY: usize = 64 * 1024;
+
+// Adds the request id to the error bodies so a user can quote it in a bug report.
+// Plain text errors, from the handlers or the axum extractors, become
+// `{"error": ..., "request_id": ...}` like the JSON ones.
+pub async fn request_id_in_errors(request: Request<Body>, next: Next) -> Response {
+    let request_id = request
+        .headers()
+        .get("x-request-id")
+        .and_then(|value| value.to_str().ok())
+        .map(String::from);
+    let response = next.run(request).await;
+    if response.status().as_u16() < 400 {
+        return response;
+    }
+
+    let content_type = response.headers().get(header::CONTENT_TYPE).map(|value| value.as_bytes().to_vec());
+    let is_json = content_type.as_deref().is_some_and(|value| value.starts_with(b"application/json"));
+    let is_text = content_type.as_deref().is_some_and(|value| value.starts_with(b"text/plain"));
+    if !is_json && !is_text {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let bytes = match to_bytes(body, MAX_ERROR_BODY).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            tracing::warn!("Failed to read the error body: {}", e);
+            return Response::from_parts(parts, Body::empty());
+        }
+    };
+
+    let object = if is_text {
+        let error = ErrorResponse { error: String::from_utf8_lossy(&bytes).into_owned(), request_id: None };
+        serde_json::to_value(error).ok()
+    } else {
+        serde_json::from_slice::<serde_json::Value>(&bytes).ok()
+    };
+
+    let body = match object {
+        Some(serde_json::Value::Object(mut object)) => {
+            if let Some(request_id) = request_id {
+                object.insert("request_id".to_string(), request_id.into());
+            }
+            let body = serde_json::Value::Object(object).to_string();
+            parts.headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("application/json"));
+            parts.headers.insert(header::CONTENT_LENGTH, HeaderValue::from(body.len()));
+            Body::from(body)
+        }
+        _ => Body::from(bytes),
+    };
+
+    Response::from_parts(parts, body)
+}
+
+// Extractor for the x-request-id of the request, set by SetRequestIdLayer
+#[derive(Clone)]
+pub struct RequestId(pub Option<String>);
+
+impl<S> FromRequestParts<S> for RequestId
+where S: Send + Sync {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let request_id = parts
+            .headers
+            .get("x-request-id")
+            .and_then(|value| value.to_str().ok())
+            .map(String::from);
+        Ok(RequestId(request_id))
+    }
+}
@@ -0,0 +1,93 @@
+use axum::{
+    body::Body, extract::State, http::{Request, StatusCode}, middleware::Next, response::Response, Json
+};
+use serde_json::json;
+
+use crate::middleware::auth_middleware::Claims;
+
+pub const TASKS_READ: &str = "tasks:read";
+pub const TASKS_WRITE: &str = "tasks:write";
+pub const USER_READ: &str = "user:read";
+pub const PROJECTS_READ: &str = "projects:read";
+pub const PROJECTS_WRITE: &str = "projects:write";
+pub const NOTIFICATIONS_READ: &str = "notifications:read";
+pub const NOTIFICATIONS_WRITE: &str = "notifications:write";
+pub const WEBHOOKS_READ: &str = "webhooks:read";
+pub const WEBHOOKS_WRITE: &str = "webhooks:write";
+
+// Scopes granted to a token issued by a normal login
+pub const DEFAULT_SCOPES: [&str; 9] = [
+    TASKS_READ, TASKS_WRITE, USER_READ, PROJECTS_READ, PROJECTS_WRITE, NOTIFICATIONS_READ, NOTIFICATIONS_WRITE,
+    WEBHOOKS_READ, WEBHOOKS_WRITE,
+];
+
+pub fn default_scopes() -> Vec<String> {
+    DEFAULT_SCOPES.iter().map(|scope| scope.to_string()).collect()
+}
+
+// The scopes of a token a login asks for, all the default ones when it asks for
+// none in particular. An unknown scope is refused rather than dropped so a typo
+// doesn't silently grant less than expected.
+pub fn requested_scopes(requested: Option<&[String]>) -> Result<Vec<String>, String> {
+    let Some(requested) = requested else {
+        return Ok(default_scopes());
+    };
+    if let Some(unknown) = requested.iter().find(|scope| !DEFAULT_SCOPES.contains(&scope.as_str())) {
+        return Err(format!("Unknown scope {}, expected some of {}", unknown, DEFAULT_SCOPES.join(", ")));
+    }
+    if requested.is_empty() {
+        return Err("Ask for at least one scope".to_string());
+    }
+    Ok(DEFAULT_SCOPES
+        .iter()
+        .filter(|scope| requested.iter().any(|requested| requested == *scope))
+        .map(|scope| scope.to_string())
+        .collect())
+}
+
+// Has to run after auth_middleware so the Claims are already in the extensions
+// Usage: .layer(middleware::from_fn_with_state(TASKS_WRITE, require_scope))
+pub async fn require_scope(
+    State(scope): State<&'static str>,
+    request: Request<Body>,
+    next: Next,
+) -> Result<Response, (StatusCode, Json<serde_json::Value>)> {
+    let claims = match request.extensions().get::<Claims>() {
+        Some(claims) => claims,
+        None => {
+            return Err((
+                StatusCode::UNAUTHORIZED,
+                Json(json!({"error": "Unauthorized"}))
+            ));
+        }
+    };
+
+    if !claims.scopes.iter().any(|granted| granted == scope) {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(json!({"error": "Missing required scope", "scope": scope}))
+        ));
+    }
+
+    Ok(next.run(request).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scopes(scopes: &[&str]) -> Vec<String> {
+        scopes.iter().map(|scope| scope.to_string()).collect()
+    }
+
+    #[test]
+    fn a_login_gets_the_scopes_it_asks_for() {
+        assert_eq!(requested_scopes(None).unwrap(), default_scopes());
+        assert_eq!(
+            requested_scopes(Some(&scopes(&["user:read", "tasks:read", "tasks:read"]))).unwrap(),
+            scopes(&["tasks:read", "user:read"]),
+        );
+        assert!(requested_scopes(Some(&scopes(&["tasks:read", "admin"]))).is_err());
+        assert!(requested_scopes(Some(&[])).is_err());
+    }
+}
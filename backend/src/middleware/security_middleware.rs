@@ -0,0 +1,75 @@
+use axum::{
+    body::Body, extract::State, http::{header, HeaderMap, HeaderName, HeaderValue, Method, Request}, middleware::Next, response::Response
+};
+use std::{sync::Arc, time::Duration};
+use tower_http::cors::{AllowOrigin, CorsLayer};
+
+use crate::utils::config::{CorsConfig, SecurityHeadersConfig};
+
+// None when no origin is allowed, browsers then block cross-origin calls.
+// The entries were checked in Config::validate().
+pub fn cors_layer(config: &CorsConfig) -> Option<CorsLayer> {
+    if config.allowed_origins.is_empty() {
+        return None;
+    }
+
+    let origins = if config.allowed_origins.iter().any(|origin| origin == "*") {
+        AllowOrigin::any()
+    } else {
+        AllowOrigin::list(config.allowed_origins.iter().map(|origin| origin.parse().unwrap()))
+    };
+    let methods: Vec<Method> = config.allowed_methods.iter().map(|method| method.parse().unwrap()).collect();
+    let headers: Vec<HeaderName> = config.allowed_headers.iter().map(|name| name.parse().unwrap()).collect();
+
+    Some(CorsLayer::new()
+        .allow_origin(origins)
+        .allow_methods(methods)
+        .allow_headers(headers)
+        .allow_credentials(config.allow_credentials)
+        .expose_headers([
+            HeaderName::from_static("x-request-id"),
+            HeaderName::from_static("retry-after"),
+            HeaderName::from_static("ratelimit-limit"),
+            HeaderName::from_static("ratelimit-remaining"),
+            HeaderName::from_static("ratelimit-reset"),
+        ])
+        .max_age(Duration::from_secs(config.max_age_secs)))
+}
+
+// The headers added to every response, built once at startup
+pub fn security_headers_from_config(config: &SecurityHeadersConfig) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+
+    if config.hsts_max_age_secs > 0 {
+        let mut hsts = format!("max-age={}", config.hsts_max_age_secs);
+        if config.hsts_include_subdomains {
+            hsts.push_str("; includeSubDomains");
+        }
+        headers.insert(header::STRICT_TRANSPORT_SECURITY, HeaderValue::from_str(&hsts).unwrap());
+    }
+    if !config.frame_options.is_empty() {
+        headers.insert(header::X_FRAME_OPTIONS, HeaderValue::from_str(&config.frame_options).unwrap());
+    }
+    if config.content_type_options {
+        headers.insert(header::X_CONTENT_TYPE_OPTIONS, HeaderValue::from_static("nosniff"));
+    }
+    if !config.referrer_policy.is_empty() {
+        headers.insert(header::REFERRER_POLICY, HeaderValue::from_str(&config.referrer_policy).unwrap());
+    }
+    headers
+}
+
+// Adds the security headers, a handler can still set its own value
+pub async fn security_headers(
+    State(headers): State<Arc<HeaderMap>>,
+    request: Request<Body>,
+    next: Next,
+) -> Response {
+    let mut response = next.run(request).await;
+    for (name, value) in headers.iter() {
+        if !response.headers().contains_key(name) {
+            response.headers_mut().insert(name, value.clone());
+        }
+    }
+    response
+}
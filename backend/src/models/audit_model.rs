@@ -0,0 +1,58 @@
+use serde::{Serialize, Deserialize};
+use std::fmt::Debug;
+use std::clone::Clone;
+use bson::{oid::ObjectId, Bson, DateTime};
+use utoipa::ToSchema;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, ToSchema)]
+pub enum AuditAction {
+    #[serde(rename = "task.created")]
+    TaskCreated,
+    #[serde(rename = "task.updated")]
+    TaskUpdated,
+    // moved to the trash
+    #[serde(rename = "task.deleted")]
+    TaskDeleted,
+    #[serde(rename = "task.restored")]
+    TaskRestored,
+    #[serde(rename = "task.assigned")]
+    TaskAssigned,
+    #[serde(rename = "task.unassigned")]
+    TaskUnassigned,
+    #[serde(rename = "user.registered")]
+    UserRegistered,
+    #[serde(rename = "user.logged_in")]
+    UserLoggedIn,
+    // a login upgraded the stored password hash
+    #[serde(rename = "user.password_rehashed")]
+    PasswordRehashed,
+}
+
+// One changed field, ids and dates are stored as strings. Null stands for a
+// field that is not set.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FieldChange {
+    pub field: String,
+    pub before: Bson,
+    pub after: Bson,
+}
+
+// An immutable record of a change, only ever inserted and purged once it is
+// older than the configured retention
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AuditEvent {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub audit_id: Option<ObjectId>,
+    pub action: AuditAction,
+    // who made the change, the new user for a registration
+    pub actor_id: String,
+    pub actor_name: String,
+    // the changed task, none for user events
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub task_id: Option<ObjectId>,
+    #[serde(default)]
+    pub changes: Vec<FieldChange>,
+    // x-request-id of the request that made the change
+    pub request_id: Option<String>,
+    pub created_at: DateTime,
+}
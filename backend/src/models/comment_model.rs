@@ -0,0 +1,26 @@
+use serde::{Serialize, Deserialize};
+use std::fmt::Debug;
+use std::clone::Clone;
+use bson::{oid::ObjectId, DateTime};
+use crate::models::mention_model::Mention;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Comment {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub comment_id: Option<ObjectId>,
+    pub task_id: ObjectId,
+    pub author_id: String,
+    // username at the time of posting
+    pub author_name: String,
+    // Markdown, stored as written and rendered by the clients
+    pub body: String,
+    // resolved @usernames of the body
+    #[serde(default)]
+    pub mentions: Vec<Mention>,
+    pub created_at: DateTime,
+    #[serde(default)]
+    pub edited_at: Option<DateTime>,
+    // deleted comments are kept as tombstones without a body so threads keep their shape
+    #[serde(default)]
+    pub deleted_at: Option<DateTime>,
+}
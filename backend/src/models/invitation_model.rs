@@ -0,0 +1,20 @@
+use serde::{Serialize, Deserialize};
+use std::fmt::Debug;
+use std::clone::Clone;
+use bson::{oid::ObjectId, DateTime};
+use crate::models::project_model::Role;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Invitation {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub invitation_id: Option<ObjectId>,
+    pub project_id: ObjectId,
+    // kept so the invitee sees what they are invited to before joining
+    pub project_name: String,
+    pub email: String,
+    pub role: Role,
+    pub invited_by: String,
+    // "pending", "accepted" or "declined"
+    pub status: String,
+    pub created_at: DateTime,
+}
@@ -0,0 +1,11 @@
+use serde::{Serialize, Deserialize};
+use std::fmt::Debug;
+use std::clone::Clone;
+use utoipa::ToSchema;
+
+// An @username in a text that was resolved to a user with access to the task
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, ToSchema)]
+pub struct Mention {
+    pub user_id: String,
+    pub username: String,
+}
@@ -1,2 +1,9 @@
+pub mod audit_model;
+pub mod comment_model;
+pub mod invitation_model;
+pub mod mention_model;
+pub mod notification_model;
+pub mod project_model;
 pub mod user_model;
-pub mod task_model;
\ No newline at end of file
+pub mod task_model;
+pub mod webhook_model;
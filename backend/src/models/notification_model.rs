@@ -0,0 +1,75 @@
+use serde::{Serialize, Deserialize};
+use std::fmt::Debug;
+use std::clone::Clone;
+use bson::{oid::ObjectId, DateTime};
+use utoipa::ToSchema;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationKind {
+    // the user was assigned to the task
+    Assigned,
+    // someone commented on a task the user created or is assigned to
+    Commented,
+    // the user was @mentioned in a description or a comment
+    Mentioned,
+    // the status of a task the user created or is assigned to changed
+    StatusChanged,
+    // a task the user created or is assigned to is due soon
+    DueSoon,
+}
+
+// Which kinds of notifications the user gets, all of them by default
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+#[serde(default)]
+pub struct NotificationPreferences {
+    pub assigned: bool,
+    pub commented: bool,
+    pub mentioned: bool,
+    pub status_changed: bool,
+    pub due_soon: bool,
+}
+
+impl Default for NotificationPreferences {
+    fn default() -> Self {
+        NotificationPreferences {
+            assigned: true,
+            commented: true,
+            mentioned: true,
+            status_changed: true,
+            due_soon: true,
+        }
+    }
+}
+
+impl NotificationPreferences {
+    pub fn allows(&self, kind: NotificationKind) -> bool {
+        match kind {
+            NotificationKind::Assigned => self.assigned,
+            NotificationKind::Commented => self.commented,
+            NotificationKind::Mentioned => self.mentioned,
+            NotificationKind::StatusChanged => self.status_changed,
+            NotificationKind::DueSoon => self.due_soon,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Notification {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub notification_id: Option<ObjectId>,
+    // who gets it
+    pub user_id: String,
+    pub kind: NotificationKind,
+    pub task_id: ObjectId,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub comment_id: Option<ObjectId>,
+    // who caused it, none for reminders like due_soon
+    #[serde(default)]
+    pub actor_id: Option<String>,
+    #[serde(default)]
+    pub actor_name: Option<String>,
+    pub created_at: DateTime,
+    #[serde(default)]
+    pub read: bool,
+}
@@ -0,0 +1,58 @@
+use serde::{Serialize, Deserialize};
+use std::fmt::Debug;
+use std::clone::Clone;
+use bson::oid::ObjectId;
+use utoipa::ToSchema;
+
+// Ordered from the least to the most rights, each role can do what the ones before it can
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Viewer,
+    Commenter,
+    Editor,
+    Owner,
+}
+
+impl Role {
+    // as stored in MongoDB
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Role::Viewer => "viewer",
+            Role::Commenter => "commenter",
+            Role::Editor => "editor",
+            Role::Owner => "owner",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ProjectMember {
+    pub user_id: String,
+    pub role: Role,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Project {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub project_id: Option<ObjectId>,
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    // archived projects are hidden from the list and take no new tasks
+    #[serde(default)]
+    pub archived: bool,
+    pub owner_id: String,
+    // everyone else with access, the owner is not listed here
+    #[serde(default)]
+    pub members: Vec<ProjectMember>,
+}
+
+impl Project {
+    pub fn role_of(&self, user_id: &str) -> Option<Role> {
+        if self.owner_id == user_id {
+            return Some(Role::Owner);
+        }
+        self.members.iter().find(|member| member.user_id == user_id).map(|member| member.role)
+    }
+}
@@ -1,13 +1,49 @@
 use serde::{Serialize, Deserialize};
 use std::fmt::Debug;
 use std::clone::Clone;
-use bson::oid::ObjectId;
+use bson::{doc, oid::ObjectId, DateTime, Document};
+use crate::models::mention_model::Mention;
 
 #[derive (Serialize, Deserialize, Debug, Clone)]
 pub struct Task {
     #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
     pub task_id: Option<ObjectId>,
     pub taskname: String,
-    pub user_id: ObjectId,
-    pub status: String,   
+    // Markdown
+    #[serde(default)]
+    pub description: String,
+    // resolved @usernames of the description
+    #[serde(default)]
+    pub mentions: Vec<Mention>,
+    pub status: String,
+    pub user_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub project_id: Option<ObjectId>,
+    // who created the task, empty for tasks created before it was recorded
+    #[serde(default)]
+    pub created_by: String,
+    // who works on it, only users with access to the task
+    #[serde(default)]
+    pub assignee_ids: Vec<String>,
+    // comments that are not deleted, kept up to date by the comment handlers
+    #[serde(default)]
+    pub comment_count: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub due_at: Option<DateTime>,
+    // set once the due_soon notifications were sent, reset when due_at changes
+    #[serde(default)]
+    pub due_notified: bool,
+    // set while the task is in the trash, it is purged after the configured retention
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<DateTime>,
+    // who put it in the trash
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deleted_by: Option<String>,
+}
+
+// Restricts a task filter to the tasks that are not in the trash, every task
+// query goes through it unless it is about the trash
+pub fn not_deleted(mut filter: Document) -> Document {
+    filter.insert("deleted_at", doc! { "$exists": false });
+    filter
 }
\ No newline at end of file
@@ -2,6 +2,7 @@ use serde::{Serialize, Deserialize};
 use std::fmt::Debug;
 use std::clone::Clone;
 use bson::oid::ObjectId;
+use crate::models::notification_model::NotificationPreferences;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct User {
@@ -10,5 +11,7 @@ pub struct User {
     pub username: String,
     pub email: String,
     pub password: String,
-    pub tasks: Vec<ObjectId>
-}
\ No newline at end of file
+    pub tasks: Vec<ObjectId>,
+    #[serde(default)]
+    pub notification_preferences: NotificationPreferences,
+}
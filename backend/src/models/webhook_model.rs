@@ -0,0 +1,56 @@
+use serde::{Serialize, Deserialize};
+use std::fmt::Debug;
+use std::clone::Clone;
+use bson::{oid::ObjectId, DateTime};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Webhook {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub webhook_id: Option<ObjectId>,
+    // the owner, it gets the events of the tasks they can see
+    pub user_id: String,
+    pub url: String,
+    // HMAC-SHA256 key of the signature header
+    pub secret: String,
+    // e.g. "task.created", empty for every event
+    #[serde(default)]
+    pub events: Vec<String>,
+    pub active: bool,
+    // failed attempts in a row, the webhook is disabled past the configured limit
+    #[serde(default)]
+    pub consecutive_failures: i32,
+    pub created_at: DateTime,
+    #[serde(default)]
+    pub disabled_at: Option<DateTime>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DeliveryAttempt {
+    pub at: DateTime,
+    // none when no response came back
+    pub status_code: Option<u16>,
+    pub latency_ms: i64,
+    // start of the response body
+    pub response_snippet: String,
+    pub error: Option<String>,
+}
+
+// A queued event for a webhook and the attempts to deliver it
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WebhookDelivery {
+    #[serde(rename = "_id")]
+    pub delivery_id: ObjectId,
+    pub webhook_id: ObjectId,
+    // the task of a task event, none for a ping
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub task_id: Option<ObjectId>,
+    pub event: String,
+    // the JSON body, the same for every attempt
+    pub payload: String,
+    // "pending", "succeeded", "failed" or "cancelled" (the webhook was disabled or deleted, or the task purged)
+    pub status: String,
+    #[serde(default)]
+    pub attempts: Vec<DeliveryAttempt>,
+    pub next_attempt_at: DateTime,
+    pub created_at: DateTime,
+}
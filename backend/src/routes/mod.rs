@@ -1 +1,2 @@
+pub mod openapi;
 pub mod router;
\ No newline at end of file
@@ -0,0 +1,164 @@
+use axum::Json;
+use serde::Serialize;
+use std::fs;
+use utoipa::{
+    openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme},
+    Modify, OpenApi, ToSchema,
+};
+
+use crate::controller::{audit_controller, auth_controller, comment_controller, event_controller, health_controller, legacy_controller, member_controller, notification_controller, oidc_controller, project_controller, task_controller, webhook_controller};
+
+// The committed copy of the spec, client SDKs are generated from it
+const SPEC_FILE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/openapi.json");
+
+// How bson serializes an ObjectId to JSON
+#[derive(Serialize, ToSchema)]
+#[schema(as = ObjectId)]
+pub struct ObjectIdSchema {
+    #[serde(rename = "$oid")]
+    pub oid: String,
+}
+
+// The JSON error body. The middleware (auth, scopes, rate limits) builds it
+// directly, the plain text errors of the handlers are wrapped into it by
+// request_id_in_errors.
+#[derive(Serialize, ToSchema)]
+pub struct ErrorResponse {
+    pub error: String,
+    pub request_id: Option<String>,
+}
+
+struct BearerAuth;
+
+impl Modify for BearerAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "bearer",
+            SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).bearer_format("JWT").build()),
+        );
+    }
+}
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "RustManager API", description = "Task management API"),
+    paths(
+        auth_controller::register,
+        auth_controller::login,
+        auth_controller::get_user_data,
+        auth_controller::jwks,
+        task_controller::all_for_user,
+        task_controller::create_task,
+        task_controller::get_task,
+        task_controller::patch_task,
+        task_controller::update_task,
+        task_controller::delete_task,
+        task_controller::assigned_to_me,
+        task_controller::assign_task,
+        task_controller::unassign_task,
+        task_controller::list_trash,
+        task_controller::restore_task,
+        audit_controller::task_history,
+        audit_controller::my_activity,
+        comment_controller::list_comments,
+        comment_controller::create_comment,
+        comment_controller::update_comment,
+        comment_controller::delete_comment,
+        event_controller::task_events,
+        project_controller::list_projects,
+        project_controller::create_project,
+        project_controller::get_project,
+        project_controller::update_project,
+        project_controller::delete_project,
+        project_controller::project_tasks,
+        project_controller::project_stats,
+        member_controller::list_members,
+        member_controller::update_member,
+        member_controller::remove_member,
+        member_controller::invite_member,
+        member_controller::list_invitations,
+        member_controller::accept_invitation,
+        member_controller::decline_invitation,
+        notification_controller::list_notifications,
+        notification_controller::mark_read,
+        notification_controller::mark_all_read,
+        notification_controller::get_preferences,
+        notification_controller::update_preferences,
+        webhook_controller::list_webhooks,
+        webhook_controller::create_webhook,
+        webhook_controller::get_webhook,
+        webhook_controller::update_webhook,
+        webhook_controller::delete_webhook,
+        webhook_controller::ping_webhook,
+        webhook_controller::list_deliveries,
+        legacy_controller::user,
+        legacy_controller::create_task,
+        legacy_controller::update_task,
+        legacy_controller::all_for_user,
+        oidc_controller::oidc_login,
+        oidc_controller::oidc_callback,
+        health_controller::healthz,
+        health_controller::readyz,
+    ),
+    components(schemas(ObjectIdSchema, ErrorResponse)),
+    modifiers(&BearerAuth),
+    tags(
+        (name = "auth", description = "Registration, login and the current user"),
+        (name = "sso", description = "Login through the OpenID Connect provider"),
+        (name = "tasks", description = "Tasks of the current user"),
+        (name = "comments", description = "Discussion threads on tasks"),
+        (name = "events", description = "Live task changes"),
+        (name = "projects", description = "Projects grouping the tasks of a user"),
+        (name = "members", description = "Project members, their roles and invitations"),
+        (name = "notifications", description = "Inbox of task events and which of them the user gets"),
+        (name = "webhooks", description = "Signed HTTP callbacks for task changes and their delivery log"),
+        (name = "audit", description = "History of the changes to tasks and of each user's activity"),
+        (name = "health", description = "Probes for the orchestrator"),
+        (name = "legacy", description = "Unversioned routes, deprecated in favour of /api/v1"),
+    ),
+)]
+pub struct ApiDoc;
+
+pub fn spec_json() -> String {
+    // trailing newline so the committed file is a normal text file
+    format!("{}\n", ApiDoc::openapi().to_pretty_json().unwrap())
+}
+
+pub async fn openapi_json() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+// `backend openapi` writes openapi.json, `backend openapi --check` fails when the
+// committed file no longer matches the handlers (run in CI)
+pub fn run_command(args: &[String]) -> Result<(), String> {
+    let spec = spec_json();
+
+    if args.iter().any(|arg| arg == "--check") {
+        let committed = fs::read_to_string(SPEC_FILE)
+            .map_err(|e| format!("Failed to read {}: {}", SPEC_FILE, e))?;
+        if committed != spec {
+            return Err(format!("{} is out of date, regenerate it with `cargo run -- openapi`", SPEC_FILE));
+        }
+        println!("{} is up to date", SPEC_FILE);
+        return Ok(());
+    }
+
+    fs::write(SPEC_FILE, spec).map_err(|e| format!("Failed to write {}: {}", SPEC_FILE, e))?;
+    println!("Wrote {}", SPEC_FILE);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn committed_spec_matches_the_handlers() {
+        // assert! rather than assert_eq!, the diff of two whole specs is unreadable
+        assert!(
+            spec_json() == include_str!("../../openapi.json"),
+            "openapi.json is out of date, regenerate it with `cargo run -- openapi`",
+        );
+    }
+}
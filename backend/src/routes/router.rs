@@ -1,29 +1,341 @@
-use axum::{middleware, routing::{get, patch, post}, Router};
-use mongodb::{Database};
+use axum::{extract::DefaultBodyLimit, handler::Handler, http::StatusCode, middleware, routing::{delete, get, patch, post}, Router};
+use std::{sync::Arc, time::Duration};
+use utoipa::OpenApi;
+use utoipa_scalar::{Scalar, Servable};
+use tower_http::{
+    request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer},
+    timeout::TimeoutLayer,
+    trace::TraceLayer,
+};
 
-use crate::controller::{auth_controller::*, task_controller::*};
+use crate::controller::{audit_controller::*, auth_controller::*, comment_controller::*, event_controller::task_events, health_controller::*, legacy_controller, member_controller::*, metrics_controller::metrics, notification_controller::*, oidc_controller::*, project_controller::*, task_controller::*, webhook_controller::*};
 use crate::middleware::auth_middleware::auth_middleware;
+use crate::middleware::deprecation_middleware::deprecated;
+use crate::routes::openapi::{openapi_json, ApiDoc};
+use crate::middleware::metrics_middleware::track_metrics;
+use crate::middleware::rate_limit_middleware::rate_limit;
+use crate::middleware::request_id_middleware::request_id_in_errors;
+use crate::middleware::scope_middleware::{
+    require_scope, NOTIFICATIONS_READ, NOTIFICATIONS_WRITE, PROJECTS_READ, PROJECTS_WRITE, TASKS_READ, TASKS_WRITE, USER_READ,
+    WEBHOOKS_READ, WEBHOOKS_WRITE,
+};
+use crate::middleware::security_middleware::{cors_layer, security_headers, security_headers_from_config};
+use crate::utils::{logging::{make_request_span, on_response}, state::AppState};
 
-pub async fn create_router(db: Database) -> Router {
-    Router::new()
+pub async fn create_router(state: AppState) -> Router {
+    let limiter = state.rate_limiter.clone();
+    // per route so it runs after auth_middleware and sees the Claims
+    let rate_limited = || middleware::from_fn_with_state(limiter.clone(), rate_limit);
+    let public_metrics = state.metrics.is_some() && state.config.metrics_addr().is_none();
+    let server_config = &state.config.server;
+    let headers = Arc::new(security_headers_from_config(&state.config.security_headers));
+    let cors = cors_layer(&state.config.cors);
+
+    let public = Router::new()
         .route("/", get(|| async {"Hello World"}))
-        .route("/user/register", post(register))
-        .route("/user/login", post(login))
+        .route("/user/register", post(register).layer(rate_limited()))
+        .route("/user/login", post(login).layer(rate_limited()))
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .route("/.well-known/jwks.json", get(jwks))
+        .route("/auth/oidc/login", get(oidc_login))
+        .route("/auth/oidc/callback", get(oidc_callback))
+        .route("/openapi.json", get(openapi_json))
+        .merge(Scalar::with_url("/docs", ApiDoc::openapi()));
 
+    // route_layer: auth runs once, only for these routes and not for unmatched paths
+    #[allow(deprecated)]
+    let protected = Router::new()
         .route("/protected", get(|| async { "Protected Route" }))
-        .layer(middleware::from_fn(auth_middleware))
+        .route("/user", get(legacy_controller::user)
+            .layer(middleware::from_fn_with_state(USER_READ, require_scope))
+            .layer(rate_limited())
+            .layer(middleware::from_fn_with_state("/api/v1/me", deprecated)))
+        .route("/task/create", post(legacy_controller::create_task)
+            .layer(middleware::from_fn_with_state(TASKS_WRITE, require_scope))
+            .layer(rate_limited())
+            .layer(middleware::from_fn_with_state("/api/v1/tasks", deprecated)))
+        .route("/task/update/{task_id}", patch(legacy_controller::update_task)
+            .layer(middleware::from_fn_with_state(TASKS_WRITE, require_scope))
+            .layer(rate_limited())
+            .layer(middleware::from_fn_with_state("/api/v1/tasks/{task_id}", deprecated)))
+        .route("/task/getAll", get(legacy_controller::all_for_user)
+            .layer(middleware::from_fn_with_state(TASKS_READ, require_scope))
+            .layer(rate_limited())
+            .layer(middleware::from_fn_with_state("/api/v1/tasks", deprecated)))
+        .route_layer(middleware::from_fn_with_state(state.keyring.clone(), auth_middleware));
+
+    let router = public
+        .merge(protected)
+        .nest("/api/v1", api_v1(&state));
+
+    // without an admin address /metrics stays on the public port behind the metrics token
+    let router = if public_metrics {
+        router.route("/metrics", get(metrics))
+    } else {
+        router
+    };
+
+    let router = router.layer(DefaultBodyLimit::max(server_config.body_limit_bytes));
+    let router = match server_config.request_timeout_secs {
+        0 => router,
+        secs => router.layer(TimeoutLayer::with_status_code(StatusCode::REQUEST_TIMEOUT, Duration::from_secs(secs))),
+    };
+    // outside auth so preflight requests and error responses get the CORS headers
+    let router = match cors {
+        Some(cors) => router.layer(cors),
+        None => router,
+    };
+
+    router
+        // the last layer added runs first: the request id is set before the span is created
+        .layer(middleware::from_fn_with_state(headers, security_headers))
+        .layer(middleware::from_fn(track_metrics))
+        .layer(middleware::from_fn(request_id_in_errors))
+        .layer(PropagateRequestIdLayer::x_request_id())
+        .layer(TraceLayer::new_for_http()
+            .make_span_with(make_request_span)
+            .on_request(())
+            .on_response(on_response))
+        .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid))
+        .with_state(state)
+}
+
+// Every /api/v1 route needs a token, auth runs once before the scope and rate limit layers
+fn api_v1(state: &AppState) -> Router<AppState> {
+    let limiter = state.rate_limiter.clone();
+    let rate_limited = || middleware::from_fn_with_state(limiter.clone(), rate_limit);
+    let scope = |scope| middleware::from_fn_with_state(scope, require_scope);
+
+    Router::new()
+        .route("/tasks", get(all_for_user.layer(scope(TASKS_READ)))
+            .post(create_task.layer(scope(TASKS_WRITE)))
+            .layer(rate_limited()))
+        .route("/tasks/{task_id}", get(get_task.layer(scope(TASKS_READ)))
+            .patch(patch_task.layer(scope(TASKS_WRITE)))
+            .put(update_task.layer(scope(TASKS_WRITE)))
+            .delete(delete_task.layer(scope(TASKS_WRITE)))
+            .layer(rate_limited()))
+        .route("/tasks/assigned", get(assigned_to_me.layer(scope(TASKS_READ)))
+            .layer(rate_limited()))
+        .route("/tasks/{task_id}/assignees", post(assign_task.layer(scope(TASKS_WRITE)))
+            .layer(rate_limited()))
+        .route("/tasks/{task_id}/assignees/{user_id}", delete(unassign_task.layer(scope(TASKS_WRITE)))
+            .layer(rate_limited()))
+        .route("/tasks/{task_id}/restore", post(restore_task.layer(scope(TASKS_WRITE)))
+            .layer(rate_limited()))
+        .route("/trash", get(list_trash.layer(scope(TASKS_READ)))
+            .layer(rate_limited()))
+        .route("/tasks/{task_id}/history", get(task_history.layer(scope(TASKS_READ)))
+            .layer(rate_limited()))
+        .route("/tasks/{task_id}/comments", get(list_comments.layer(scope(TASKS_READ)))
+            .post(create_comment.layer(scope(TASKS_WRITE)))
+            .layer(rate_limited()))
+        .route("/tasks/{task_id}/comments/{comment_id}", patch(update_comment.layer(scope(TASKS_WRITE)))
+            .delete(delete_comment.layer(scope(TASKS_WRITE)))
+            .layer(rate_limited()))
+        .route("/projects", get(list_projects.layer(scope(PROJECTS_READ)))
+            .post(create_project.layer(scope(PROJECTS_WRITE)))
+            .layer(rate_limited()))
+        .route("/projects/{project_id}", get(get_project.layer(scope(PROJECTS_READ)))
+            .patch(update_project.layer(scope(PROJECTS_WRITE)))
+            .delete(delete_project.layer(scope(PROJECTS_WRITE)))
+            .layer(rate_limited()))
+        .route("/projects/{project_id}/tasks", get(project_tasks.layer(scope(TASKS_READ)))
+            .layer(rate_limited()))
+        .route("/projects/{project_id}/stats", get(project_stats.layer(scope(PROJECTS_READ)))
+            .layer(rate_limited()))
+        .route("/projects/{project_id}/members", get(list_members.layer(scope(PROJECTS_READ)))
+            .layer(rate_limited()))
+        .route("/projects/{project_id}/members/{user_id}", patch(update_member.layer(scope(PROJECTS_WRITE)))
+            .delete(remove_member.layer(scope(PROJECTS_WRITE)))
+            .layer(rate_limited()))
+        .route("/projects/{project_id}/invitations", post(invite_member.layer(scope(PROJECTS_WRITE)))
+            .layer(rate_limited()))
+        .route("/invitations", get(list_invitations.layer(scope(PROJECTS_READ)))
+            .layer(rate_limited()))
+        .route("/invitations/{invitation_id}/accept", post(accept_invitation.layer(scope(PROJECTS_WRITE)))
+            .layer(rate_limited()))
+        .route("/invitations/{invitation_id}/decline", post(decline_invitation.layer(scope(PROJECTS_WRITE)))
+            .layer(rate_limited()))
+        .route("/webhooks", get(list_webhooks.layer(scope(WEBHOOKS_READ)))
+            .post(create_webhook.layer(scope(WEBHOOKS_WRITE)))
+            .layer(rate_limited()))
+        .route("/webhooks/{webhook_id}", get(get_webhook.layer(scope(WEBHOOKS_READ)))
+            .patch(update_webhook.layer(scope(WEBHOOKS_WRITE)))
+            .delete(delete_webhook.layer(scope(WEBHOOKS_WRITE)))
+            .layer(rate_limited()))
+        .route("/webhooks/{webhook_id}/ping", post(ping_webhook.layer(scope(WEBHOOKS_WRITE)))
+            .layer(rate_limited()))
+        .route("/webhooks/{webhook_id}/deliveries", get(list_deliveries.layer(scope(WEBHOOKS_READ)))
+            .layer(rate_limited()))
+        .route("/events", get(task_events.layer(scope(TASKS_READ)))
+            .layer(rate_limited()))
+        .route("/me", get(get_user_data.layer(scope(USER_READ)))
+            .layer(rate_limited()))
+        .route("/me/activity", get(my_activity.layer(scope(USER_READ)))
+            .layer(rate_limited()))
+        .route("/me/notification-preferences", get(get_preferences.layer(scope(NOTIFICATIONS_READ)))
+            .put(update_preferences.layer(scope(NOTIFICATIONS_WRITE)))
+            .layer(rate_limited()))
+        .route("/notifications", get(list_notifications.layer(scope(NOTIFICATIONS_READ)))
+            .layer(rate_limited()))
+        .route("/notifications/read-all", post(mark_all_read.layer(scope(NOTIFICATIONS_WRITE)))
+            .layer(rate_limited()))
+        .route("/notifications/{notification_id}/read", post(mark_read.layer(scope(NOTIFICATIONS_WRITE)))
+            .layer(rate_limited()))
+        .route_layer(middleware::from_fn_with_state(state.keyring.clone(), auth_middleware))
+}
+
+// Served on metrics.admin_bind_address, away from the public port
+pub fn create_admin_router(state: AppState) -> Router {
+    Router::new()
+        .route("/metrics", get(metrics))
+        .with_state(state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::{to_bytes, Body}, http::{header, Request, Response}};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use tower::ServiceExt;
+
+    use crate::middleware::{auth_middleware::Claims, scope_middleware::default_scopes};
+    use crate::utils::{
+        config::Config, events::EventBus, keyring::{Keyring, SigningKey}, password::Passwords, rate_limit::RateLimiter,
+    };
+
+    // The client connects lazily, these requests are answered before any query
+    async fn test_state() -> AppState {
+        let config = Config::default();
+        let client = mongodb::Client::with_uri_str("mongodb://127.0.0.1:1").await.unwrap();
+        AppState {
+            db: client.database("router-tests"),
+            keyring: Arc::new(Keyring::new(SigningKey::generate("test").unwrap(), vec![])),
+            oidc: None,
+            passwords: Arc::new(Passwords::from_config(&config.passwords).unwrap()),
+            metrics: None,
+            rate_limiter: Arc::new(RateLimiter::new(config.rate_limit.clone())),
+            events: Arc::new(EventBus::new(16)),
+            draining: Arc::new(AtomicBool::new(false)),
+            config: Arc::new(config),
+        }
+    }
+
+    fn token(state: &AppState) -> String {
+        token_with(state, default_scopes())
+    }
+
+    fn token_with(state: &AppState, scopes: Vec<String>) -> String {
+        let now = chrono::Utc::now().timestamp() as usize;
+        let claims = Claims {
+            user_id: "65a000000000000000000001".to_string(),
+            username: "alice".to_string(),
+            scopes,
+            exp: now + 3600,
+            iat: now,
+        };
+        state.keyring.sign(&claims).unwrap()
+    }
+
+    async fn send(router: Router, request: Request<Body>) -> (StatusCode, String) {
+        let response: Response<Body> = router.oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    fn json_post(uri: &str, body: &str) -> Request<Body> {
+        Request::post(uri)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn register_and_login_need_no_token() {
+        let state = test_state().await;
+
+        let register = json_post("/user/register", r#"{"username": "", "email": "", "password": ""}"#);
+        let (status, body) = send(create_router(state.clone()).await, register).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body.contains("The fields can't be empty"), "{}", body);
+
+        let login = json_post("/user/login", r#"{"email": "", "password": ""}"#);
+        let (status, body) = send(create_router(state.clone()).await, login).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body.contains("All fields are required"), "{}", body);
+
+        assert_eq!(state.keyring.verifications.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn protected_routes_decode_the_token_once() {
+        let state = test_state().await;
+        let bearer = format!("Bearer {}", token(&state));
+
+        let requests = [
+            Request::get("/api/v1/tasks/not-an-id/history"),
+            Request::patch("/task/update/not-an-id").header(header::CONTENT_TYPE, "application/json"),
+        ];
+        let bodies = ["", r#"{"taskname": "a", "status": "Pending"}"#];
+
+        for (request, body) in requests.into_iter().zip(bodies) {
+            let before = state.keyring.verifications.load(Ordering::SeqCst);
+            let request = request.header(header::AUTHORIZATION, &bearer).body(Body::from(body)).unwrap();
+            let (status, body) = send(create_router(state.clone()).await, request).await;
+
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{}", body);
+            assert_eq!(state.keyring.verifications.load(Ordering::SeqCst) - before, 1);
+        }
+    }
+
+    #[tokio::test]
+    async fn a_token_without_the_scope_is_forbidden() {
+        let state = test_state().await;
+        let bearer = format!("Bearer {}", token_with(&state, vec!["tasks:read".to_string()]));
+
+        let request = json_post("/api/v1/tasks", r#"{"taskname": "a", "status": "Pending"}"#);
+        let (mut parts, body) = request.into_parts();
+        parts.headers.insert(header::AUTHORIZATION, bearer.parse().unwrap());
+        let (status, body) = send(create_router(state).await, Request::from_parts(parts, body)).await;
+
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let body: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(body["scope"], "tasks:write");
+    }
+
+    #[tokio::test]
+    async fn login_refuses_an_unknown_scope() {
+        let state = test_state().await;
+        let login = json_post("/user/login", r#"{"email": "a@b.c", "password": "secret", "scopes": ["tasks:admin"]}"#);
+        let (status, body) = send(create_router(state).await, login).await;
 
-        .route("/user", get(get_user_data))
-        .layer(middleware::from_fn(auth_middleware))
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body.contains("Unknown scope tasks:admin"), "{}", body);
+    }
 
-        .route("/task/create", post(create_task))
-        .layer(middleware::from_fn(auth_middleware))
+    #[tokio::test]
+    async fn legacy_routes_link_their_successor() {
+        let state = test_state().await;
+        let request = Request::patch("/task/update/not-an-id")
+            .header(header::CONTENT_TYPE, "application/json")
+            .header(header::AUTHORIZATION, format!("Bearer {}", token(&state)))
+            .body(Body::from(r#"{"taskname": "a", "status": "Pending"}"#))
+            .unwrap();
+        let response = create_router(state).await.oneshot(request).await.unwrap();
+        let headers = response.headers();
 
-        .route("/task/update/{task_id}", patch(update_task))
-        .layer(middleware::from_fn(auth_middleware))
+        assert_eq!(headers["Deprecation"], "@1792368000");
+        assert_eq!(headers["Sunset"], "Fri, 30 Apr 2027 00:00:00 GMT");
+        assert_eq!(headers[header::LINK], "</api/v1/tasks/not-an-id>; rel=\"successor-version\"");
+    }
 
-        .route("/task/getAll", get(all_for_user))
-        .layer(middleware::from_fn(auth_middleware))
-        
-        .with_state(db)
-}
\ No newline at end of file
+    #[tokio::test]
+    async fn protected_routes_reject_a_missing_token() {
+        let state = test_state().await;
+        let (status, _) = send(create_router(state).await, Request::get("/api/v1/tasks").body(Body::empty()).unwrap()).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+}
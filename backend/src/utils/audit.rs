@@ -0,0 +1,199 @@
+use bson::{doc, oid::ObjectId, Bson, DateTime};
+use mongodb::Database;
+use std::time::Duration;
+
+use crate::middleware::{auth_middleware::Claims, request_id_middleware::RequestId};
+use crate::models::{
+    audit_model::{AuditAction, AuditEvent, FieldChange}, task_model::Task, user_model::User
+};
+use crate::utils::config::AuditConfig;
+
+// The task fields kept in the history, mentions follow from the description and
+// the others are bookkeeping
+const TASK_FIELDS: [&str; 9] = [
+    "taskname", "description", "status", "user_id", "project_id", "assignee_ids", "due_at", "deleted_at", "deleted_by",
+];
+
+// Who makes a change and in which request
+pub struct Actor {
+    pub user_id: String,
+    pub username: String,
+    pub request_id: Option<String>,
+}
+
+impl Actor {
+    pub fn new(claims: &Claims, RequestId(request_id): RequestId) -> Self {
+        Actor { user_id: claims.user_id.clone(), username: claims.username.clone(), request_id }
+    }
+
+    // for the auth events, which happen before there is a token
+    pub fn user(user: &User, RequestId(request_id): RequestId) -> Self {
+        Actor {
+            user_id: user.user_id.map(|id| id.to_hex()).unwrap_or_default(),
+            username: user.username.clone(),
+            request_id,
+        }
+    }
+}
+
+// The tracked fields that differ, `None` is a task that doesn't exist yet or anymore
+pub fn task_changes(before: Option<&Task>, after: Option<&Task>) -> Vec<FieldChange> {
+    let before = before.and_then(|task| bson::to_document(task).ok()).unwrap_or_default();
+    let after = after.and_then(|task| bson::to_document(task).ok()).unwrap_or_default();
+
+    TASK_FIELDS
+        .iter()
+        .filter_map(|field| {
+            let before = plain(before.get(*field).cloned().unwrap_or(Bson::Null));
+            let after = plain(after.get(*field).cloned().unwrap_or(Bson::Null));
+            (before != after).then(|| FieldChange { field: field.to_string(), before, after })
+        })
+        .collect()
+}
+
+// ids and dates as the strings the API returns
+fn plain(value: Bson) -> Bson {
+    match value {
+        Bson::ObjectId(id) => Bson::String(id.to_hex()),
+        Bson::DateTime(at) => Bson::String(at.try_to_rfc3339_string().unwrap_or_default()),
+        Bson::Array(values) => Bson::Array(values.into_iter().map(plain).collect()),
+        value => value,
+    }
+}
+
+// Records a change. A failure is only logged, the change has already been made.
+pub async fn record(
+    db: &Database,
+    actor: &Actor,
+    action: AuditAction,
+    task_id: Option<ObjectId>,
+    changes: Vec<FieldChange>,
+) {
+    let event = AuditEvent {
+        audit_id: None,
+        action,
+        actor_id: actor.user_id.clone(),
+        actor_name: actor.username.clone(),
+        task_id,
+        changes,
+        request_id: actor.request_id.clone(),
+        created_at: DateTime::now(),
+    };
+    if let Err(e) = db.collection::<AuditEvent>("audit").insert_one(event).await {
+        tracing::error!("Failed to record the audit event: {}", e);
+    }
+}
+
+// Records a change to a task, an update that left the tracked fields as they
+// were is not recorded
+pub async fn record_task(
+    db: &Database,
+    actor: &Actor,
+    action: AuditAction,
+    task_id: ObjectId,
+    before: Option<&Task>,
+    after: Option<&Task>,
+) {
+    if let Some(changes) = recorded_changes(before, after) {
+        record(db, actor, action, Some(task_id), changes).await;
+    }
+}
+
+// The changes to record for a task, none for an update that changed no tracked field
+fn recorded_changes(before: Option<&Task>, after: Option<&Task>) -> Option<Vec<FieldChange>> {
+    let changes = task_changes(before, after);
+    (!changes.is_empty() || before.is_none() || after.is_none()).then_some(changes)
+}
+
+// Periodically purges the audit events older than the retention
+pub async fn audit_sweeper(db: Database, config: AuditConfig) {
+    let mut interval = tokio::time::interval(Duration::from_secs(config.sweep_interval_secs));
+    let retention_ms = (config.retention_days as i64).saturating_mul(24 * 60 * 60 * 1000);
+
+    loop {
+        interval.tick().await;
+        let cutoff = DateTime::from_millis(DateTime::now().timestamp_millis().saturating_sub(retention_ms));
+        match db.collection::<AuditEvent>("audit").delete_many(doc! { "created_at": { "$lt": cutoff } }).await {
+            Ok(result) if result.deleted_count > 0 => {
+                tracing::info!("Purged {} expired audit events", result.deleted_count);
+            }
+            Ok(_) => {}
+            Err(e) => tracing::error!("Failed to purge the expired audit events: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn task() -> Task {
+        Task {
+            task_id: Some(ObjectId::new()),
+            taskname: "Write the report".to_string(),
+            description: String::new(),
+            mentions: vec![],
+            status: "Pending".to_string(),
+            user_id: "creator".to_string(),
+            project_id: None,
+            created_by: "creator".to_string(),
+            assignee_ids: vec![],
+            comment_count: 0,
+            due_at: None,
+            due_notified: false,
+            deleted_at: None,
+            deleted_by: None,
+        }
+    }
+
+    fn fields(changes: &[FieldChange]) -> Vec<&str> {
+        changes.iter().map(|change| change.field.as_str()).collect()
+    }
+
+    #[test]
+    fn a_new_task_records_its_set_fields() {
+        let mut created = task();
+        let project_id = ObjectId::new();
+        created.project_id = Some(project_id);
+        created.assignee_ids = vec!["assignee".to_string()];
+
+        let changes = recorded_changes(None, Some(&created)).unwrap();
+        // absent fields are left out, an empty description is still set
+        assert_eq!(fields(&changes), vec!["taskname", "description", "status", "user_id", "project_id", "assignee_ids"]);
+        assert_eq!(changes[1].before, Bson::Null);
+        assert_eq!(changes[1].after, Bson::String(String::new()));
+        // ids are stored as the strings the API returns
+        assert_eq!(changes[4].after, Bson::String(project_id.to_hex()));
+        assert_eq!(changes[5].after, Bson::Array(vec![Bson::String("assignee".to_string())]));
+    }
+
+    #[test]
+    fn an_update_that_changes_nothing_is_not_recorded() {
+        let before = task();
+        let mut after = before.clone();
+        // not a tracked field
+        after.comment_count = 3;
+
+        assert!(recorded_changes(Some(&before), Some(&after)).is_none());
+    }
+
+    #[test]
+    fn moving_to_the_trash_records_only_when_and_by_whom() {
+        let before = task();
+        let mut after = before.clone();
+        let deleted_at = DateTime::from_millis(1_792_368_000_000);
+        after.deleted_at = Some(deleted_at);
+        after.deleted_by = Some("editor".to_string());
+
+        let changes = recorded_changes(Some(&before), Some(&after)).unwrap();
+        assert_eq!(fields(&changes), vec!["deleted_at", "deleted_by"]);
+        assert_eq!(changes[0].before, Bson::Null);
+        assert_eq!(changes[0].after, Bson::String(deleted_at.try_to_rfc3339_string().unwrap()));
+        assert_eq!(changes[1].after, Bson::String("editor".to_string()));
+
+        // restoring is the same change the other way
+        let changes = recorded_changes(Some(&after), Some(&before)).unwrap();
+        assert_eq!(fields(&changes), vec!["deleted_at", "deleted_by"]);
+        assert_eq!(changes[0].after, Bson::Null);
+    }
+}
@@ -0,0 +1,333 @@
+use axum::http::StatusCode;
+use bson::{doc, oid::ObjectId};
+use futures::TryStreamExt;
+use mongodb::{Collection, Database};
+
+use crate::models::{project_model::{Project, Role}, task_model::{not_deleted, Task}};
+
+// Every check on who may do what with a project or a task goes through here,
+// handlers never compare user ids themselves.
+
+#[derive(Clone, Copy, Debug)]
+pub enum Action {
+    // read the project, its tasks and members
+    View,
+    // comment on the tasks
+    Comment,
+    // create, change and delete tasks
+    Edit,
+    // rename, archive or delete the project and manage its members
+    Manage,
+}
+
+impl Action {
+    fn required_role(self) -> Role {
+        match self {
+            Action::View => Role::Viewer,
+            Action::Comment => Role::Commenter,
+            Action::Edit => Role::Editor,
+            Action::Manage => Role::Owner,
+        }
+    }
+
+    fn verb(self) -> &'static str {
+        match self {
+            Action::View => "view",
+            Action::Comment => "comment on",
+            Action::Edit => "edit",
+            Action::Manage => "manage",
+        }
+    }
+}
+
+pub fn allows(role: Option<Role>, action: Action) -> bool {
+    role.is_some_and(|role| role >= action.required_role())
+}
+
+fn forbidden(action: Action, what: &str) -> (StatusCode, String) {
+    (StatusCode::FORBIDDEN, format!("Not allowed to {} this {}", action.verb(), what))
+}
+
+// Loads a project the user may do `action` on
+pub async fn authorize_project(
+    db: &Database,
+    project_id: &str,
+    user_id: &str,
+    action: Action,
+) -> Result<(ObjectId, Project), (StatusCode, String)> {
+    let obj_id = match ObjectId::parse_str(project_id) {
+        Ok(id) => id,
+        Err(_) => return Err((StatusCode::BAD_REQUEST, "Invalid Project Id".to_string())),
+    };
+
+    let project = project_access(load_project(db, obj_id).await?, user_id, action)?;
+    Ok((obj_id, project))
+}
+
+// 404 for a missing project, 403 for one the user may not do `action` on
+fn project_access(project: Option<Project>, user_id: &str, action: Action) -> Result<Project, (StatusCode, String)> {
+    let project = project.ok_or_else(|| (StatusCode::NOT_FOUND, "There is no project with this id".to_string()))?;
+    if !allows(project.role_of(user_id), action) {
+        return Err(forbidden(action, "project"));
+    }
+    Ok(project)
+}
+
+// Loads a task the user may do `action` on. The role in the task's project
+// decides, only a task outside any project is its creator's alone. Tasks in
+// the trash are not found.
+pub async fn authorize_task(
+    db: &Database,
+    task_id: &str,
+    user_id: &str,
+    action: Action,
+) -> Result<(ObjectId, Task), (StatusCode, String)> {
+    find_task(db, task_id, user_id, action, false).await
+}
+
+// Same for a task in the trash
+pub async fn authorize_trashed_task(
+    db: &Database,
+    task_id: &str,
+    user_id: &str,
+    action: Action,
+) -> Result<(ObjectId, Task), (StatusCode, String)> {
+    find_task(db, task_id, user_id, action, true).await
+}
+
+async fn find_task(
+    db: &Database,
+    task_id: &str,
+    user_id: &str,
+    action: Action,
+    trashed: bool,
+) -> Result<(ObjectId, Task), (StatusCode, String)> {
+    let obj_id = match ObjectId::parse_str(task_id) {
+        Ok(id) => id,
+        Err(_) => return Err((StatusCode::BAD_REQUEST, "Invalid Task Id".to_string())),
+    };
+
+    let collection: Collection<Task> = db.collection("task");
+    let filter = if trashed {
+        doc! { "_id": obj_id, "deleted_at": { "$exists": true } }
+    } else {
+        not_deleted(doc! { "_id": obj_id })
+    };
+    let task = match collection.find_one(filter).await {
+        Ok(task) => task,
+        Err(e) => {
+            tracing::error!("There is some error in finding the task: {}", e);
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "There is some error in finding the task".to_string(),
+            ));
+        }
+    };
+
+    let project = match task.as_ref().and_then(|task| task.project_id) {
+        Some(project_id) => load_project(db, project_id).await?,
+        None => None,
+    };
+    Ok((obj_id, task_access(task, project.as_ref(), user_id, action)?))
+}
+
+// 404 for a missing task, 403 for one the user may not do `action` on
+fn task_access(task: Option<Task>, project: Option<&Project>, user_id: &str, action: Action) -> Result<Task, (StatusCode, String)> {
+    let task = task.ok_or_else(|| {
+        (StatusCode::NOT_FOUND, "The task id is not valid or there is not task with this id".to_string())
+    })?;
+    if !allows_on_task(&task, project, user_id, action) {
+        return Err(forbidden(action, "task"));
+    }
+    Ok(task)
+}
+
+// `project` is the task's project, none when it is in none or it was deleted
+fn allows_on_task(task: &Task, project: Option<&Project>, user_id: &str, action: Action) -> bool {
+    match task.project_id {
+        // a creator removed from the project or demoted keeps no extra right
+        Some(_) => allows(project.and_then(|project| project.role_of(user_id)), action),
+        None => task.user_id == user_id,
+    }
+}
+
+// Whether the user may do `action` on an already loaded task
+pub async fn task_allows(db: &Database, task: &Task, user_id: &str, action: Action) -> Result<bool, (StatusCode, String)> {
+    let project = match task.project_id {
+        Some(project_id) => load_project(db, project_id).await?,
+        None => None,
+    };
+    Ok(allows_on_task(task, project.as_ref(), user_id, action))
+}
+
+// The projects the user may do `action` on, for queries across projects
+pub async fn projects_allowing(db: &Database, user_id: &str, action: Action) -> Result<Vec<ObjectId>, (StatusCode, String)> {
+    let collection: Collection<Project> = db.collection("project");
+    let projects: Vec<Project> = match collection
+        .find(doc! { "$or": [{ "owner_id": user_id }, { "members.user_id": user_id }] })
+        .await
+    {
+        Ok(cursor) => cursor.try_collect().await,
+        Err(e) => Err(e),
+    }
+    .map_err(|e| {
+        tracing::error!("There is some error in listing the projects: {}", e);
+        (StatusCode::INTERNAL_SERVER_ERROR, "There is some error in listing the projects".to_string())
+    })?;
+
+    Ok(allowing(projects, user_id, action))
+}
+
+fn allowing(projects: Vec<Project>, user_id: &str, action: Action) -> Vec<ObjectId> {
+    projects
+        .into_iter()
+        .filter(|project| allows(project.role_of(user_id), action))
+        .filter_map(|project| project.project_id)
+        .collect()
+}
+
+// The assignees of a task that can still see it once it is in `project`,
+// or in none, where only its creator can
+pub fn assignees_with_access(task: &Task, project: Option<&Project>) -> Vec<String> {
+    task.assignee_ids
+        .iter()
+        .filter(|assignee_id| match project {
+            Some(project) => allows(project.role_of(assignee_id), Action::View),
+            None => **assignee_id == task.user_id,
+        })
+        .cloned()
+        .collect()
+}
+
+// The users who can see a task: everyone in its project, or its creator when
+// it is in none
+pub async fn task_viewers(db: &Database, task: &Task) -> Result<Vec<String>, (StatusCode, String)> {
+    let mut viewers = Vec::new();
+    match task.project_id {
+        Some(project_id) => {
+            if let Some(project) = load_project(db, project_id).await? {
+                viewers.push(project.owner_id);
+                viewers.extend(project.members.into_iter().map(|member| member.user_id));
+            }
+        }
+        None => viewers.push(task.user_id.clone()),
+    }
+    viewers.sort();
+    viewers.dedup();
+    Ok(viewers)
+}
+
+async fn load_project(db: &Database, project_id: ObjectId) -> Result<Option<Project>, (StatusCode, String)> {
+    let collection: Collection<Project> = db.collection("project");
+    collection.find_one(doc! {"_id": project_id}).await.map_err(|e| {
+        tracing::error!("There is some error in finding the project: {}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "There is some error in finding the project".to_string(),
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::project_model::ProjectMember;
+
+    fn task(assignee_ids: &[&str]) -> Task {
+        Task {
+            task_id: Some(ObjectId::new()),
+            taskname: "Write the report".to_string(),
+            description: String::new(),
+            mentions: vec![],
+            status: "Pending".to_string(),
+            user_id: "creator".to_string(),
+            project_id: None,
+            created_by: "creator".to_string(),
+            assignee_ids: assignee_ids.iter().map(|id| id.to_string()).collect(),
+            comment_count: 0,
+            due_at: None,
+            due_notified: false,
+            deleted_at: None,
+            deleted_by: None,
+        }
+    }
+
+    fn project(owner_id: &str, members: &[(&str, Role)]) -> Project {
+        Project {
+            project_id: Some(ObjectId::new()),
+            name: "Launch".to_string(),
+            description: String::new(),
+            archived: false,
+            owner_id: owner_id.to_string(),
+            members: members
+                .iter()
+                .map(|(user_id, role)| ProjectMember { user_id: user_id.to_string(), role: *role })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn moving_a_task_unassigns_who_cannot_see_the_new_project() {
+        let task = task(&["creator", "viewer", "outsider", "owner"]);
+        let project = project("owner", &[("viewer", Role::Viewer), ("creator", Role::Editor)]);
+
+        assert_eq!(assignees_with_access(&task, Some(&project)), vec!["creator", "viewer", "owner"]);
+        // outside any project only the creator has access
+        assert_eq!(assignees_with_access(&task, None), vec!["creator"]);
+    }
+
+    #[test]
+    fn each_role_can_do_what_the_ones_before_it_can() {
+        let roles = [None, Some(Role::Viewer), Some(Role::Commenter), Some(Role::Editor), Some(Role::Owner)];
+        let actions = [Action::View, Action::Comment, Action::Edit, Action::Manage];
+        for (rank, role) in roles.iter().enumerate() {
+            for (needed, action) in actions.iter().enumerate() {
+                assert_eq!(allows(*role, *action), rank > needed, "{:?} on {:?}", role, action);
+            }
+        }
+    }
+
+    #[test]
+    fn a_missing_project_is_404_and_a_forbidden_one_403() {
+        let project = project("owner", &[("viewer", Role::Viewer), ("editor", Role::Editor)]);
+
+        assert_eq!(project_access(None, "owner", Action::View).unwrap_err().0, StatusCode::NOT_FOUND);
+        assert_eq!(project_access(Some(project.clone()), "outsider", Action::View).unwrap_err().0, StatusCode::FORBIDDEN);
+        assert_eq!(project_access(Some(project.clone()), "viewer", Action::Edit).unwrap_err().0, StatusCode::FORBIDDEN);
+        assert_eq!(project_access(Some(project.clone()), "editor", Action::Manage).unwrap_err().0, StatusCode::FORBIDDEN);
+        assert!(project_access(Some(project.clone()), "editor", Action::Edit).is_ok());
+        assert!(project_access(Some(project), "owner", Action::Manage).is_ok());
+    }
+
+    #[test]
+    fn the_project_role_decides_on_a_task_not_its_creator() {
+        assert_eq!(task_access(None, None, "creator", Action::View).unwrap_err().0, StatusCode::NOT_FOUND);
+
+        // outside any project only the creator has access
+        let personal = task(&[]);
+        assert!(task_access(Some(personal.clone()), None, "creator", Action::Edit).is_ok());
+        assert_eq!(task_access(Some(personal), None, "someone", Action::View).unwrap_err().0, StatusCode::FORBIDDEN);
+
+        let project = project("owner", &[("creator", Role::Viewer), ("editor", Role::Editor)]);
+        let mut shared = task(&[]);
+        shared.project_id = project.project_id;
+        assert_eq!(
+            task_access(Some(shared.clone()), Some(&project), "creator", Action::Edit).unwrap_err().0,
+            StatusCode::FORBIDDEN,
+        );
+        assert!(task_access(Some(shared.clone()), Some(&project), "creator", Action::View).is_ok());
+        assert!(task_access(Some(shared.clone()), Some(&project), "editor", Action::Edit).is_ok());
+        // the project is gone, nobody keeps a right through it
+        assert!(!allows_on_task(&shared, None, "owner", Action::View));
+    }
+
+    #[test]
+    fn only_projects_with_the_right_are_listed() {
+        let owned = project("me", &[]);
+        let edited = project("owner", &[("me", Role::Editor)]);
+        let viewed = project("owner", &[("me", Role::Viewer)]);
+        let expected = vec![owned.project_id.unwrap(), edited.project_id.unwrap()];
+
+        assert_eq!(allowing(vec![owned, edited, viewed], "me", Action::Edit), expected);
+    }
+}
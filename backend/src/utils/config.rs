@@ -0,0 +1,756 @@
+use axum::http::{HeaderName, HeaderValue, Method};
+use serde::Deserialize;
+use std::{collections::HashMap, env, fs, net::{IpAddr, SocketAddr}, str::FromStr};
+
+// Typed configuration, loaded once at startup.
+// Values come from the TOML file named by CONFIG_FILE (if set), then the
+// environment variables override them.
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub database: DatabaseConfig,
+    pub auth: AuthConfig,
+    pub passwords: PasswordConfig,
+    pub oidc: Option<OidcConfig>,
+    pub cors: CorsConfig,
+    pub security_headers: SecurityHeadersConfig,
+    pub logging: LoggingConfig,
+    pub metrics: MetricsConfig,
+    pub tls: Option<TlsConfig>,
+    pub rate_limit: RateLimitConfig,
+    pub comments: CommentsConfig,
+    pub notifications: NotificationsConfig,
+    pub events: EventsConfig,
+    pub webhooks: WebhooksConfig,
+    pub audit: AuditConfig,
+    pub trash: TrashConfig,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    // IPv4 or IPv6 address
+    pub bind_address: String,
+    pub port: u16,
+    // listen on this unix socket instead of TCP, e.g. behind a sidecar proxy
+    pub unix_socket: Option<String>,
+    // time /readyz fails before the listener is closed on shutdown
+    pub readiness_delay_ms: u64,
+    // in-flight requests still running after this are cut
+    pub drain_timeout_ms: u64,
+    // bigger request bodies are rejected with 413
+    pub body_limit_bytes: usize,
+    // requests without a response after this get 408, 0 disables the timeout
+    pub request_timeout_secs: u64,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseConfig {
+    pub uri: String,
+    pub name: String,
+    // startup gives up after this many failed pings
+    pub connect_attempts: u32,
+    // timeout of the ping done at startup and by /readyz
+    pub ping_timeout_ms: u64,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    pub token_expiry_hours: i64,
+    pub password_login_enabled: bool,
+    // PEM file of the Ed25519 signing key, required unless ephemeral_signing_key is set
+    pub signing_key: Option<String>,
+    // development only, a key generated at startup that dies with the process
+    pub ephemeral_signing_key: bool,
+    pub signing_kid: String,
+    // retired keys as `kid=path@not_after`, not_after in RFC 3339
+    pub previous_keys: Vec<String>,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct PasswordConfig {
+    pub argon2_memory_kib: u32,
+    pub argon2_iterations: u32,
+    pub argon2_parallelism: u32,
+    pub min_length: usize,
+    pub breached_passwords_file: Option<String>,
+}
+
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct OidcConfig {
+    pub issuer: String,
+    pub client_id: String,
+    pub client_secret: Option<String>,
+    pub redirect_uri: String,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct CorsConfig {
+    // CORS is disabled when empty, "*" allows any origin
+    pub allowed_origins: Vec<String>,
+    pub allowed_methods: Vec<String>,
+    pub allowed_headers: Vec<String>,
+    pub allow_credentials: bool,
+    // how long browsers may cache a preflight response
+    pub max_age_secs: u64,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct SecurityHeadersConfig {
+    // Strict-Transport-Security max-age, 0 leaves the header out
+    pub hsts_max_age_secs: u64,
+    pub hsts_include_subdomains: bool,
+    // X-Frame-Options, "" leaves the header out
+    pub frame_options: String,
+    pub content_type_options: bool,
+    // Referrer-Policy, "" leaves the header out
+    pub referrer_policy: String,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoggingConfig {
+    // "text" or "json"
+    pub format: String,
+    // default filter when RUST_LOG is not set
+    pub level: String,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct TlsConfig {
+    pub cert_path: String,
+    pub key_path: String,
+    // how often the files are checked for changes, 0 disables the reload
+    pub reload_interval_secs: u64,
+}
+
+impl Default for TlsConfig {
+    fn default() -> Self {
+        TlsConfig {
+            cert_path: String::new(),
+            key_path: String::new(),
+            reload_interval_secs: 30,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct RouteLimit {
+    // sustained rate the bucket refills at
+    pub requests_per_minute: u32,
+    // bucket size, the number of requests allowed at once
+    pub burst: u32,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitConfig {
+    pub enabled: bool,
+    // key anonymous requests by the last X-Forwarded-For entry, only behind a trusted proxy
+    pub trust_forwarded_for: bool,
+    pub default: RouteLimit,
+    // per route overrides, keyed by method and route pattern e.g. "PATCH /task/update/{task_id}",
+    // or by the route pattern alone for every method
+    pub routes: HashMap<String, RouteLimit>,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        RateLimitConfig {
+            enabled: true,
+            trust_forwarded_for: false,
+            default: RouteLimit { requests_per_minute: 120, burst: 60 },
+            routes: HashMap::from([
+                ("POST /user/login".to_string(), RouteLimit { requests_per_minute: 10, burst: 5 }),
+                ("POST /user/register".to_string(), RouteLimit { requests_per_minute: 5, burst: 5 }),
+                ("POST /task/create".to_string(), RouteLimit { requests_per_minute: 30, burst: 10 }),
+                ("POST /api/v1/tasks".to_string(), RouteLimit { requests_per_minute: 30, burst: 10 }),
+            ]),
+        }
+    }
+}
+
+impl RateLimitConfig {
+    pub fn limit_for(&self, method: &str, route: &str) -> RouteLimit {
+        self.routes
+            .get(&format!("{} {}", method, route))
+            .or_else(|| self.routes.get(route))
+            .copied()
+            .unwrap_or(self.default)
+    }
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct CommentsConfig {
+    // authors can edit or delete a comment for this long after posting it
+    pub edit_window_secs: u64,
+    // in bytes of Markdown
+    pub max_length: usize,
+}
+
+impl Default for CommentsConfig {
+    fn default() -> Self {
+        CommentsConfig {
+            edit_window_secs: 15 * 60,
+            max_length: 10_000,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct NotificationsConfig {
+    // tasks due within this many hours notify their creator and assignees
+    pub due_soon_hours: u64,
+    // how often to look for tasks due soon, 0 disables the due_soon notifications
+    pub due_sweep_interval_secs: u64,
+}
+
+impl Default for NotificationsConfig {
+    fn default() -> Self {
+        NotificationsConfig {
+            due_soon_hours: 24,
+            due_sweep_interval_secs: 300,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct EventsConfig {
+    // recent task events kept for streams resuming with Last-Event-ID
+    pub history_size: usize,
+    // comment sent on idle streams so proxies don't close them
+    pub keep_alive_secs: u64,
+}
+
+impl Default for EventsConfig {
+    fn default() -> Self {
+        EventsConfig {
+            history_size: 1000,
+            keep_alive_secs: 15,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct WebhooksConfig {
+    // how often the queue is checked for due deliveries, 0 disables sending
+    pub poll_interval_secs: u64,
+    pub timeout_secs: u64,
+    pub max_attempts: u32,
+    // delay before the first retry, doubled for each next one
+    pub retry_base_secs: u64,
+    // failed attempts in a row after which a webhook is disabled
+    pub disable_after_failures: u32,
+    // accept plain http:// URLs, e.g. for a local stand-in
+    pub allow_http: bool,
+    // deliveries sent at the same time, so a slow receiver doesn't hold up the others
+    pub concurrency: usize,
+    // host names or IPs that may resolve to loopback, private or link-local
+    // addresses, which are refused otherwise, e.g. "127.0.0.1" for a local stand-in
+    pub allowed_hosts: Vec<String>,
+}
+
+impl Default for WebhooksConfig {
+    fn default() -> Self {
+        WebhooksConfig {
+            poll_interval_secs: 5,
+            timeout_secs: 10,
+            max_attempts: 8,
+            retry_base_secs: 30,
+            disable_after_failures: 20,
+            allow_http: false,
+            concurrency: 8,
+            allowed_hosts: vec![],
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuditConfig {
+    // audit events older than this are purged, 0 keeps them forever
+    pub retention_days: u64,
+    // how often the expired events are purged
+    pub sweep_interval_secs: u64,
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        AuditConfig {
+            retention_days: 365,
+            sweep_interval_secs: 3600,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct TrashConfig {
+    // deleted tasks are purged for good after this, 0 keeps them in the trash forever
+    pub retention_days: u64,
+    // how often the expired tasks are purged
+    pub sweep_interval_secs: u64,
+}
+
+impl Default for TrashConfig {
+    fn default() -> Self {
+        TrashConfig {
+            retention_days: 30,
+            sweep_interval_secs: 3600,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct MetricsConfig {
+    pub enabled: bool,
+    // `/metrics` is served on this separate address, set it to "" to serve it on
+    // the public port where the token is required
+    pub admin_bind_address: Option<String>,
+    pub token: Option<String>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            bind_address: "127.0.0.1".to_string(),
+            port: 3000,
+            unix_socket: None,
+            readiness_delay_ms: 0,
+            drain_timeout_ms: 30000,
+            body_limit_bytes: 1024 * 1024,
+            request_timeout_secs: 30,
+        }
+    }
+}
+
+impl ServerConfig {
+    pub fn bind_addr(&self) -> SocketAddr {
+        // validated in Config::validate()
+        let ip: IpAddr = self.bind_address.parse().unwrap();
+        SocketAddr::new(ip, self.port)
+    }
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        DatabaseConfig {
+            uri: String::new(),
+            name: "RustManager".to_string(),
+            connect_attempts: 10,
+            ping_timeout_ms: 2000,
+        }
+    }
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        AuthConfig {
+            token_expiry_hours: 24,
+            password_login_enabled: true,
+            signing_key: None,
+            ephemeral_signing_key: false,
+            signing_kid: "primary".to_string(),
+            previous_keys: vec![],
+        }
+    }
+}
+
+impl Default for PasswordConfig {
+    fn default() -> Self {
+        PasswordConfig {
+            // OWASP minimum for Argon2id
+            argon2_memory_kib: 19456,
+            argon2_iterations: 2,
+            argon2_parallelism: 1,
+            min_length: 8,
+            breached_passwords_file: None,
+        }
+    }
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        CorsConfig {
+            allowed_origins: vec![],
+            allowed_methods: ["GET", "POST", "PUT", "PATCH", "DELETE"].map(String::from).to_vec(),
+            allowed_headers: ["authorization", "content-type", "x-request-id"].map(String::from).to_vec(),
+            allow_credentials: false,
+            max_age_secs: 600,
+        }
+    }
+}
+
+impl Default for SecurityHeadersConfig {
+    fn default() -> Self {
+        SecurityHeadersConfig {
+            hsts_max_age_secs: 31536000,
+            hsts_include_subdomains: true,
+            frame_options: "DENY".to_string(),
+            content_type_options: true,
+            referrer_policy: "no-referrer".to_string(),
+        }
+    }
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        LoggingConfig {
+            format: "text".to_string(),
+            level: "info".to_string(),
+        }
+    }
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        MetricsConfig {
+            enabled: true,
+            admin_bind_address: Some("127.0.0.1:9100".to_string()),
+            token: None,
+        }
+    }
+}
+
+// Where the overrides are read from, the process environment outside of tests
+struct Vars<'a>(&'a dyn Fn(&str) -> Option<String>);
+
+impl Vars<'_> {
+    fn has(&self, name: &str) -> bool {
+        (self.0)(name).is_some()
+    }
+
+    fn value<T: FromStr>(&self, name: &str, target: &mut T) -> Result<(), String> {
+        if let Some(value) = (self.0)(name) {
+            *target = value
+                .parse()
+                .map_err(|_| format!("{} has an invalid value: {}", name, value))?;
+        }
+        Ok(())
+    }
+
+    fn optional(&self, name: &str, target: &mut Option<String>) {
+        if let Some(value) = (self.0)(name) {
+            *target = Some(value);
+        }
+    }
+
+    fn list(&self, name: &str, target: &mut Vec<String>) {
+        if let Some(value) = (self.0)(name) {
+            *target = value
+                .split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(str::to_string)
+                .collect();
+        }
+    }
+}
+
+impl Config {
+    pub fn load() -> Result<Self, String> {
+        let file = match env::var("CONFIG_FILE") {
+            Ok(path) => {
+                let contents = fs::read_to_string(&path)
+                    .map_err(|e| format!("Failed to read the config file {}: {}", path, e))?;
+                Some((path, contents))
+            }
+            Err(_) => None,
+        };
+        let file = file.as_ref().map(|(path, contents)| (path.as_str(), contents.as_str()));
+        Config::from_sources(file, &|name| env::var(name).ok())
+    }
+
+    // The file as (path, contents) if any, then the variables on top of it
+    fn from_sources(file: Option<(&str, &str)>, vars: &dyn Fn(&str) -> Option<String>) -> Result<Self, String> {
+        let mut config = match file {
+            Some((path, contents)) => toml::from_str(contents)
+                .map_err(|e| format!("Invalid config file {}: {}", path, e))?,
+            None => Config::default(),
+        };
+
+        config.apply_env(&Vars(vars))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn apply_env(&mut self, vars: &Vars) -> Result<(), String> {
+        vars.value("BIND_ADDRESS", &mut self.server.bind_address)?;
+        vars.value("PORT", &mut self.server.port)?;
+        vars.optional("UNIX_SOCKET", &mut self.server.unix_socket);
+        vars.value("READINESS_DELAY_MS", &mut self.server.readiness_delay_ms)?;
+        vars.value("DRAIN_TIMEOUT_MS", &mut self.server.drain_timeout_ms)?;
+        vars.value("BODY_LIMIT_BYTES", &mut self.server.body_limit_bytes)?;
+        vars.value("REQUEST_TIMEOUT_SECS", &mut self.server.request_timeout_secs)?;
+
+        // `MongoDB` is the name used by older deployments
+        vars.value("MongoDB", &mut self.database.uri)?;
+        vars.value("MONGODB_URI", &mut self.database.uri)?;
+        vars.value("DB_NAME", &mut self.database.name)?;
+        vars.value("DB_CONNECT_ATTEMPTS", &mut self.database.connect_attempts)?;
+        vars.value("DB_PING_TIMEOUT_MS", &mut self.database.ping_timeout_ms)?;
+
+        vars.value("TOKEN_EXPIRY_HOURS", &mut self.auth.token_expiry_hours)?;
+        vars.value("PASSWORD_LOGIN_ENABLED", &mut self.auth.password_login_enabled)?;
+        vars.optional("JWT_SIGNING_KEY", &mut self.auth.signing_key);
+        vars.value("JWT_EPHEMERAL_KEY", &mut self.auth.ephemeral_signing_key)?;
+        vars.value("JWT_SIGNING_KID", &mut self.auth.signing_kid)?;
+        vars.list("JWT_PREVIOUS_KEYS", &mut self.auth.previous_keys);
+
+        vars.value("ARGON2_MEMORY_KIB", &mut self.passwords.argon2_memory_kib)?;
+        vars.value("ARGON2_ITERATIONS", &mut self.passwords.argon2_iterations)?;
+        vars.value("ARGON2_PARALLELISM", &mut self.passwords.argon2_parallelism)?;
+        vars.value("PASSWORD_MIN_LENGTH", &mut self.passwords.min_length)?;
+        vars.optional("BREACHED_PASSWORDS_FILE", &mut self.passwords.breached_passwords_file);
+
+        if vars.has("OIDC_ISSUER") || self.oidc.is_some() {
+            let oidc = self.oidc.get_or_insert_with(OidcConfig::default);
+            vars.value("OIDC_ISSUER", &mut oidc.issuer)?;
+            vars.value("OIDC_CLIENT_ID", &mut oidc.client_id)?;
+            vars.optional("OIDC_CLIENT_SECRET", &mut oidc.client_secret);
+            vars.value("OIDC_REDIRECT_URI", &mut oidc.redirect_uri)?;
+        }
+
+        vars.list("CORS_ALLOWED_ORIGINS", &mut self.cors.allowed_origins);
+        vars.list("CORS_ALLOWED_METHODS", &mut self.cors.allowed_methods);
+        vars.list("CORS_ALLOWED_HEADERS", &mut self.cors.allowed_headers);
+        vars.value("CORS_ALLOW_CREDENTIALS", &mut self.cors.allow_credentials)?;
+        vars.value("CORS_MAX_AGE_SECS", &mut self.cors.max_age_secs)?;
+
+        vars.value("HSTS_MAX_AGE_SECS", &mut self.security_headers.hsts_max_age_secs)?;
+        vars.value("HSTS_INCLUDE_SUBDOMAINS", &mut self.security_headers.hsts_include_subdomains)?;
+        vars.value("FRAME_OPTIONS", &mut self.security_headers.frame_options)?;
+        vars.value("CONTENT_TYPE_OPTIONS", &mut self.security_headers.content_type_options)?;
+        vars.value("REFERRER_POLICY", &mut self.security_headers.referrer_policy)?;
+
+        vars.value("LOG_FORMAT", &mut self.logging.format)?;
+        vars.value("LOG_LEVEL", &mut self.logging.level)?;
+
+        if vars.has("TLS_CERT_PATH") || self.tls.is_some() {
+            let tls = self.tls.get_or_insert_with(TlsConfig::default);
+            vars.value("TLS_CERT_PATH", &mut tls.cert_path)?;
+            vars.value("TLS_KEY_PATH", &mut tls.key_path)?;
+            vars.value("TLS_RELOAD_INTERVAL_SECS", &mut tls.reload_interval_secs)?;
+        }
+
+        vars.value("RATE_LIMIT_ENABLED", &mut self.rate_limit.enabled)?;
+        vars.value("RATE_LIMIT_TRUST_FORWARDED_FOR", &mut self.rate_limit.trust_forwarded_for)?;
+        vars.value("RATE_LIMIT_PER_MINUTE", &mut self.rate_limit.default.requests_per_minute)?;
+        vars.value("RATE_LIMIT_BURST", &mut self.rate_limit.default.burst)?;
+
+        vars.value("COMMENT_EDIT_WINDOW_SECS", &mut self.comments.edit_window_secs)?;
+        vars.value("COMMENT_MAX_LENGTH", &mut self.comments.max_length)?;
+
+        vars.value("DUE_SOON_HOURS", &mut self.notifications.due_soon_hours)?;
+        vars.value("DUE_SWEEP_INTERVAL_SECS", &mut self.notifications.due_sweep_interval_secs)?;
+
+        vars.value("EVENTS_HISTORY_SIZE", &mut self.events.history_size)?;
+        vars.value("EVENTS_KEEP_ALIVE_SECS", &mut self.events.keep_alive_secs)?;
+
+        vars.value("WEBHOOK_POLL_INTERVAL_SECS", &mut self.webhooks.poll_interval_secs)?;
+        vars.value("WEBHOOK_TIMEOUT_SECS", &mut self.webhooks.timeout_secs)?;
+        vars.value("WEBHOOK_MAX_ATTEMPTS", &mut self.webhooks.max_attempts)?;
+        vars.value("WEBHOOK_RETRY_BASE_SECS", &mut self.webhooks.retry_base_secs)?;
+        vars.value("WEBHOOK_DISABLE_AFTER_FAILURES", &mut self.webhooks.disable_after_failures)?;
+        vars.value("WEBHOOK_ALLOW_HTTP", &mut self.webhooks.allow_http)?;
+        vars.value("WEBHOOK_CONCURRENCY", &mut self.webhooks.concurrency)?;
+        vars.list("WEBHOOK_ALLOWED_HOSTS", &mut self.webhooks.allowed_hosts);
+
+        vars.value("AUDIT_RETENTION_DAYS", &mut self.audit.retention_days)?;
+        vars.value("AUDIT_SWEEP_INTERVAL_SECS", &mut self.audit.sweep_interval_secs)?;
+
+        vars.value("TRASH_RETENTION_DAYS", &mut self.trash.retention_days)?;
+        vars.value("TRASH_SWEEP_INTERVAL_SECS", &mut self.trash.sweep_interval_secs)?;
+
+        vars.value("METRICS_ENABLED", &mut self.metrics.enabled)?;
+        vars.optional("METRICS_ADMIN_BIND_ADDRESS", &mut self.metrics.admin_bind_address);
+        vars.optional("METRICS_TOKEN", &mut self.metrics.token);
+        if self.metrics.admin_bind_address.as_deref() == Some("") {
+            self.metrics.admin_bind_address = None;
+        }
+        Ok(())
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.server.bind_address.parse::<IpAddr>().is_err() {
+            return Err(format!("server.bind_address {} is not an IP address", self.server.bind_address));
+        }
+        if let Some(tls) = &self.tls {
+            if tls.cert_path.is_empty() || tls.key_path.is_empty() {
+                return Err("tls.cert_path and tls.key_path must both be set".to_string());
+            }
+            if self.server.unix_socket.is_some() {
+                return Err("TLS can't be used with server.unix_socket, terminate TLS in the proxy".to_string());
+            }
+        }
+        if self.database.uri.is_empty() {
+            return Err("database.uri (MONGODB_URI) must be set".to_string());
+        }
+        if self.database.name.is_empty() {
+            return Err("database.name (DB_NAME) can't be empty".to_string());
+        }
+        if self.database.connect_attempts == 0 || self.database.ping_timeout_ms == 0 {
+            return Err("database.connect_attempts and database.ping_timeout_ms must be positive".to_string());
+        }
+        if self.auth.token_expiry_hours <= 0 {
+            return Err("auth.token_expiry_hours must be positive".to_string());
+        }
+        if let Some(oidc) = &self.oidc
+            && (oidc.issuer.is_empty() || oidc.client_id.is_empty() || oidc.redirect_uri.is_empty())
+        {
+            return Err("oidc.issuer, oidc.client_id and oidc.redirect_uri must all be set".to_string());
+        }
+        if !self.auth.password_login_enabled && self.oidc.is_none() {
+            return Err("Password login can only be disabled when OIDC is configured".to_string());
+        }
+        if let Some(origin) = self.cors.allowed_origins.iter()
+            .find(|origin| *origin != "*" && !origin.starts_with("http://") && !origin.starts_with("https://"))
+        {
+            return Err(format!("cors.allowed_origins entry {} must be an http(s) origin or *", origin));
+        }
+        if self.cors.allow_credentials && self.cors.allowed_origins.iter().any(|origin| origin == "*") {
+            return Err("cors.allow_credentials can't be used with the * origin".to_string());
+        }
+        if let Some(method) = self.cors.allowed_methods.iter().find(|method| method.parse::<Method>().is_err()) {
+            return Err(format!("cors.allowed_methods entry {} is not an HTTP method", method));
+        }
+        if let Some(name) = self.cors.allowed_headers.iter().find(|name| name.parse::<HeaderName>().is_err()) {
+            return Err(format!("cors.allowed_headers entry {} is not a header name", name));
+        }
+        let frame_options = self.security_headers.frame_options.as_str();
+        if !["", "DENY", "SAMEORIGIN"].contains(&frame_options) {
+            return Err(format!("security_headers.frame_options must be DENY, SAMEORIGIN or empty, got {}", frame_options));
+        }
+        if HeaderValue::from_str(&self.security_headers.referrer_policy).is_err() {
+            return Err("security_headers.referrer_policy is not a valid header value".to_string());
+        }
+        if self.server.body_limit_bytes == 0 {
+            return Err("server.body_limit_bytes must be positive".to_string());
+        }
+        if self.logging.format != "text" && self.logging.format != "json" {
+            return Err(format!("logging.format must be text or json, got {}", self.logging.format));
+        }
+        let limits = std::iter::once(("default", &self.rate_limit.default))
+            .chain(self.rate_limit.routes.iter().map(|(route, limit)| (route.as_str(), limit)));
+        for (route, limit) in limits {
+            if limit.requests_per_minute == 0 || limit.burst == 0 {
+                return Err(format!("rate_limit for {} needs a positive requests_per_minute and burst", route));
+            }
+        }
+        if self.comments.max_length == 0 {
+            return Err("comments.max_length must be positive".to_string());
+        }
+        if self.notifications.due_soon_hours == 0 {
+            return Err("notifications.due_soon_hours must be positive".to_string());
+        }
+        if self.events.history_size == 0 || self.events.keep_alive_secs == 0 {
+            return Err("events.history_size and events.keep_alive_secs must be positive".to_string());
+        }
+        if self.webhooks.timeout_secs == 0
+            || self.webhooks.max_attempts == 0
+            || self.webhooks.disable_after_failures == 0
+            || self.webhooks.concurrency == 0
+        {
+            return Err("webhooks.timeout_secs, webhooks.max_attempts, webhooks.disable_after_failures and webhooks.concurrency must be positive".to_string());
+        }
+        if self.audit.retention_days > 0 && self.audit.sweep_interval_secs == 0 {
+            return Err("audit.sweep_interval_secs must be positive when audit.retention_days is set".to_string());
+        }
+        if self.trash.retention_days > 0 && self.trash.sweep_interval_secs == 0 {
+            return Err("trash.sweep_interval_secs must be positive when trash.retention_days is set".to_string());
+        }
+        if let Some(address) = &self.metrics.admin_bind_address
+            && address.parse::<SocketAddr>().is_err()
+        {
+            return Err(format!("metrics.admin_bind_address {} is not an ip:port address", address));
+        }
+        if self.metrics.enabled && self.metrics.admin_bind_address.is_none() && self.metrics.token.is_none() {
+            return Err("metrics.token must be set when /metrics is served on the public port".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn metrics_addr(&self) -> Option<SocketAddr> {
+        if !self.metrics.enabled {
+            return None;
+        }
+        // validated in validate()
+        self.metrics.admin_bind_address.as_ref().map(|address| address.parse().unwrap())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // with the one setting that has no default
+    fn load(file: Option<&str>, vars: &[(&str, &str)]) -> Result<Config, String> {
+        let lookup = |name: &str| {
+            let value = vars.iter().find(|(key, _)| *key == name).map(|(_, value)| value.to_string());
+            value.or_else(|| (name == "MONGODB_URI").then(|| "mongodb://localhost:27017".to_string()))
+        };
+        Config::from_sources(file.map(|contents| ("config.toml", contents)), &lookup)
+    }
+
+    #[test]
+    fn the_database_uri_is_required() {
+        assert!(load(None, &[]).is_ok());
+        assert_eq!(load(None, &[("MONGODB_URI", "")]).unwrap_err(), "database.uri (MONGODB_URI) must be set");
+    }
+
+    #[test]
+    fn an_unknown_key_in_the_file_is_refused() {
+        let error = load(Some("[server]\nprot = 4000\n"), &[]).unwrap_err();
+        assert!(error.starts_with("Invalid config file config.toml"), "{}", error);
+        assert!(error.contains("unknown field `prot`"), "{}", error);
+
+        let error = load(Some("[sever]\nport = 4000\n"), &[]).unwrap_err();
+        assert!(error.contains("unknown field `sever`"), "{}", error);
+    }
+
+    #[test]
+    fn an_invalid_variable_is_refused() {
+        assert_eq!(load(None, &[("PORT", "http")]).unwrap_err(), "PORT has an invalid value: http");
+        assert_eq!(
+            load(None, &[("RATE_LIMIT_ENABLED", "yes")]).unwrap_err(),
+            "RATE_LIMIT_ENABLED has an invalid value: yes",
+        );
+    }
+
+    #[test]
+    fn an_invalid_setting_is_refused_after_the_overrides() {
+        let error = load(Some("[logging]\nformat = \"json\"\n"), &[("LOG_FORMAT", "xml")]).unwrap_err();
+        assert_eq!(error, "logging.format must be text or json, got xml");
+
+        let error = load(None, &[("WEBHOOK_CONCURRENCY", "0")]).unwrap_err();
+        assert!(error.contains("webhooks.concurrency"), "{}", error);
+    }
+
+    #[test]
+    fn variables_take_precedence_over_the_file() {
+        let file = "[server]\nport = 4000\nbind_address = \"127.0.0.1\"\n\n[cors]\nallowed_origins = [\"https://a.example\"]\n";
+
+        let config = load(Some(file), &[]).unwrap();
+        assert_eq!(config.server.port, 4000);
+        assert_eq!(config.cors.allowed_origins, vec!["https://a.example"]);
+
+        let config = load(Some(file), &[("PORT", "5000"), ("CORS_ALLOWED_ORIGINS", "https://b.example, https://c.example")]).unwrap();
+        assert_eq!(config.server.port, 5000);
+        assert_eq!(config.server.bind_address, "127.0.0.1");
+        assert_eq!(config.cors.allowed_origins, vec!["https://b.example", "https://c.example"]);
+    }
+}
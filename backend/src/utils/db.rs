@@ -1,9 +1,61 @@
+use bson::doc;
 use mongodb::{options::ClientOptions, Client, Database};
-use std::env;
-
-pub async fn db_connect() -> Database {
-    let uri = env::var("MongoDB").expect("MongoDB uri is not set");
-    let client_options= ClientOptions::parse(uri).await.unwrap();
-    let client = Client::with_options(client_options).unwrap();
-    client.database("RustManager")
-}
\ No newline at end of file
+use std::time::Duration;
+
+use crate::utils::{config::DatabaseConfig, metrics::mongo_command_metrics};
+
+const MAX_BACKOFF: Duration = Duration::from_secs(10);
+
+// Connects and pings the server, retrying with exponential backoff so the app
+// can start before MongoDB is up
+pub async fn db_connect(config: &DatabaseConfig) -> Result<Database, String> {
+    let mut client_options = ClientOptions::parse(&config.uri)
+        .await
+        .map_err(|e| format!("Invalid MongoDB uri: {}", e))?;
+    client_options.command_event_handler = Some(mongo_command_metrics());
+
+    let client = Client::with_options(client_options)
+        .map_err(|e| format!("Failed to create the MongoDB client: {}", e))?;
+    let db = client.database(&config.name);
+
+    let ping_timeout = Duration::from_millis(config.ping_timeout_ms);
+    let mut backoff = Duration::from_millis(500);
+    let mut attempt = 1;
+    loop {
+        // only the probe is bounded by ping_timeout_ms, the queries keep the driver timeouts
+        let ping = match tokio::time::timeout(ping_timeout, db.run_command(doc! { "ping": 1 })).await {
+            Ok(result) => result.map_err(|e| e.to_string()),
+            Err(_) => Err(format!("ping timed out after {}ms", config.ping_timeout_ms)),
+        };
+        match ping {
+            Ok(_) => return Ok(db),
+            Err(e) if attempt < config.connect_attempts => {
+                tracing::warn!("MongoDB is not reachable (attempt {}/{}): {}, retrying in {:?}",
+                    attempt, config.connect_attempts, e, backoff);
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+                attempt += 1;
+            }
+            Err(e) => {
+                return Err(format!("MongoDB is not reachable after {} attempts: {}", attempt, e));
+            }
+        }
+    }
+}
+
+// Tasks used to store user_id as an ObjectId, the model now keeps the string
+// form the tokens carry. Converts the old documents, a no-op once done.
+pub async fn migrate_task_user_ids(db: &Database) -> Result<(), String> {
+    let result = db
+        .collection::<bson::Document>("task")
+        .update_many(
+            doc! { "user_id": { "$type": "objectId" } },
+            vec![doc! { "$set": { "user_id": { "$toString": "$user_id" } } }],
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+    if result.modified_count > 0 {
+        tracing::info!("Converted the user_id of {} tasks to a string", result.modified_count);
+    }
+    Ok(())
+}
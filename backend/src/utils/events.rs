@@ -0,0 +1,120 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use bson::oid::ObjectId;
+use tokio::sync::broadcast;
+
+use crate::models::task_model::Task;
+
+#[derive(Clone, Copy, Debug)]
+pub enum TaskEventKind {
+    Created,
+    Updated,
+    // moved to the trash
+    Deleted,
+    Restored,
+}
+
+impl TaskEventKind {
+    // the SSE event name
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TaskEventKind::Created => "task.created",
+            TaskEventKind::Updated => "task.updated",
+            TaskEventKind::Deleted => "task.deleted",
+            TaskEventKind::Restored => "task.restored",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct TaskEvent {
+    pub seq: u64,
+    pub kind: TaskEventKind,
+    pub task_id: ObjectId,
+    // the task after the change, or as it was before being deleted
+    pub task: Task,
+}
+
+// Where a stream resumes from, given its Last-Event-ID
+pub enum Resume {
+    // the events it missed, oldest first
+    Replay(Vec<Arc<TaskEvent>>),
+    // the missed events are no longer kept or the id is from another process,
+    // the client has to fetch the tasks again
+    Reset,
+}
+
+struct History {
+    next_seq: u64,
+    events: VecDeque<Arc<TaskEvent>>,
+}
+
+// In-process fan out of task changes to the event streams. The most recent
+// events are kept so a reconnecting stream can catch up. Each process has its
+// own bus, so behind a load balancer a stream only sees the changes made
+// through the same instance.
+pub struct EventBus {
+    // event ids are `{epoch}-{seq}`, the epoch tells ids of an earlier process apart
+    epoch: u128,
+    sender: broadcast::Sender<Arc<TaskEvent>>,
+    history: Mutex<History>,
+    history_size: usize,
+}
+
+impl EventBus {
+    pub fn new(history_size: usize) -> Self {
+        let epoch = SystemTime::now().duration_since(UNIX_EPOCH).map(|since| since.as_millis()).unwrap_or_default();
+        let (sender, _) = broadcast::channel(history_size);
+        EventBus {
+            epoch,
+            sender,
+            history: Mutex::new(History { next_seq: 1, events: VecDeque::with_capacity(history_size) }),
+            history_size,
+        }
+    }
+
+    pub fn event_id(&self, event: &TaskEvent) -> String {
+        format!("{}-{}", self.epoch, event.seq)
+    }
+
+    pub fn publish(&self, kind: TaskEventKind, task_id: ObjectId, task: Task) {
+        // sent under the lock so a subscriber never misses or repeats an event
+        // between its replay and the live events
+        let mut history = self.history.lock().unwrap();
+        let event = Arc::new(TaskEvent { seq: history.next_seq, kind, task_id, task });
+        history.next_seq += 1;
+        if history.events.len() == self.history_size {
+            history.events.pop_front();
+        }
+        history.events.push_back(event.clone());
+        // no receivers is fine, nobody is listening
+        let _ = self.sender.send(event);
+    }
+
+    pub fn subscribe(&self, last_event_id: Option<&str>) -> (Resume, broadcast::Receiver<Arc<TaskEvent>>) {
+        let history = self.history.lock().unwrap();
+        let receiver = self.sender.subscribe();
+
+        let Some(last_event_id) = last_event_id else {
+            return (Resume::Replay(vec![]), receiver);
+        };
+        let last_seq = last_event_id
+            .split_once('-')
+            .filter(|(epoch, _)| *epoch == self.epoch.to_string())
+            .and_then(|(_, seq)| seq.parse::<u64>().ok());
+
+        let resume = match last_seq {
+            // the next event it expects is still kept, or nothing happened since
+            Some(seq) if seq < history.next_seq
+                && history.events.front().is_none_or(|oldest| oldest.seq <= seq + 1) =>
+            {
+                Resume::Replay(history.events.iter().filter(|event| event.seq > seq).cloned().collect())
+            }
+            _ => Resume::Reset,
+        };
+        (resume, receiver)
+    }
+}
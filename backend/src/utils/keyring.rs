@@ -0,0 +1,232 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, Utc};
+use jsonwebtoken::{
+    decode, decode_header, encode,
+    errors::{Error, ErrorKind},
+    jwk::{
+        AlgorithmParameters, CommonParameters, EllipticCurve, Jwk, JwkSet, KeyAlgorithm,
+        OctetKeyPairParameters, OctetKeyPairType, PublicKeyUse,
+    },
+    Algorithm, DecodingKey, EncodingKey, Header, TokenData, Validation,
+};
+use ring::{
+    rand::SystemRandom,
+    signature::{Ed25519KeyPair, KeyPair},
+};
+use serde::{de::DeserializeOwned, Serialize};
+use std::fs;
+
+use crate::utils::config::AuthConfig;
+
+// One Ed25519 key of the ring, identified by the `kid` put in the token header
+pub struct SigningKey {
+    pub kid: String,
+    encoding_key: EncodingKey,
+    decoding_key: DecodingKey,
+    // raw public key, published as `x` in the JWKS
+    public_key: Vec<u8>,
+}
+
+impl SigningKey {
+    // `der` is a PKCS#8 encoded Ed25519 private key
+    pub fn from_pkcs8(kid: &str, der: &[u8]) -> Result<Self, String> {
+        let key_pair = Ed25519KeyPair::from_pkcs8_maybe_unchecked(der)
+            .map_err(|e| format!("Key {} is not a valid Ed25519 PKCS#8 key: {}", kid, e))?;
+        let public_key = key_pair.public_key().as_ref().to_vec();
+
+        Ok(SigningKey {
+            kid: kid.to_string(),
+            encoding_key: EncodingKey::from_ed_der(der),
+            decoding_key: DecodingKey::from_ed_der(&public_key),
+            public_key,
+        })
+    }
+
+    pub fn from_pem_file(kid: &str, path: &str) -> Result<Self, String> {
+        let contents = fs::read(path)
+            .map_err(|e| format!("Failed to read key {} from {}: {}", kid, path, e))?;
+        let parsed = pem::parse(contents)
+            .map_err(|e| format!("Key {} in {} is not valid PEM: {}", kid, path, e))?;
+        Self::from_pkcs8(kid, parsed.contents())
+    }
+
+    pub fn generate(kid: &str) -> Result<Self, String> {
+        let document = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new())
+            .map_err(|e| format!("Failed to generate key {}: {}", kid, e))?;
+        Self::from_pkcs8(kid, document.as_ref())
+    }
+
+    pub fn to_jwk(&self) -> Jwk {
+        Jwk {
+            common: CommonParameters {
+                public_key_use: Some(PublicKeyUse::Signature),
+                key_algorithm: Some(KeyAlgorithm::EdDSA),
+                key_id: Some(self.kid.clone()),
+                ..Default::default()
+            },
+            algorithm: AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+                key_type: OctetKeyPairType::OctetKeyPair,
+                curve: EllipticCurve::Ed25519,
+                x: URL_SAFE_NO_PAD.encode(&self.public_key),
+            }),
+        }
+    }
+}
+
+// A retired key, accepted for verification until `not_after`
+struct PreviousKey {
+    key: SigningKey,
+    not_after: DateTime<Utc>,
+}
+
+// Splits a previous key entry, `kid=path@not_after` with an RFC 3339 date
+pub fn parse_previous_key(entry: &str) -> Result<(&str, &str, DateTime<Utc>), String> {
+    let (kid, rest) = entry
+        .split_once('=')
+        .ok_or_else(|| format!("Previous key entry {} must be `kid=path@not_after`", entry))?;
+    let (path, not_after) = rest
+        .rsplit_once('@')
+        .ok_or_else(|| format!("Previous key entry {} must be `kid=path@not_after`", entry))?;
+    let not_after = DateTime::parse_from_rfc3339(not_after.trim())
+        .map_err(|e| format!("Previous key entry {} has an invalid not_after date: {}", entry, e))?;
+    Ok((kid.trim(), path.trim(), not_after.with_timezone(&Utc)))
+}
+
+// The active key signs new tokens, the previous keys are still accepted for
+// verification until their own not_after date
+pub struct Keyring {
+    active: SigningKey,
+    previous: Vec<PreviousKey>,
+    // tokens verified so far, the router tests check each request decodes once
+    #[cfg(test)]
+    pub verifications: std::sync::atomic::AtomicUsize,
+}
+
+impl Keyring {
+    pub fn new(active: SigningKey, previous: Vec<(SigningKey, DateTime<Utc>)>) -> Self {
+        Keyring {
+            active,
+            previous: previous.into_iter().map(|(key, not_after)| PreviousKey { key, not_after }).collect(),
+            #[cfg(test)]
+            verifications: Default::default(),
+        }
+    }
+
+    pub fn from_config(config: &AuthConfig) -> Result<Self, String> {
+        let active = match &config.signing_key {
+            Some(path) => SigningKey::from_pem_file(&config.signing_kid, path)?,
+            None if config.ephemeral_signing_key => {
+                tracing::warn!("Using an ephemeral JWT signing key, tokens will not survive a restart");
+                SigningKey::generate(&config.signing_kid)?
+            }
+            None => {
+                return Err("auth.signing_key (JWT_SIGNING_KEY) must be set, \
+                    or auth.ephemeral_signing_key (JWT_EPHEMERAL_KEY) enabled for development".to_string());
+            }
+        };
+
+        let mut previous = Vec::new();
+        for entry in &config.previous_keys {
+            let (kid, path, not_after) = parse_previous_key(entry)?;
+            previous.push((SigningKey::from_pem_file(kid, path)?, not_after));
+        }
+
+        Ok(Keyring::new(active, previous))
+    }
+
+    // the previous keys that have not expired yet
+    fn valid_previous(&self) -> impl Iterator<Item = &SigningKey> {
+        let now = Utc::now();
+        self.previous.iter().filter(move |previous| now <= previous.not_after).map(|previous| &previous.key)
+    }
+
+    fn find(&self, kid: &str) -> Option<&SigningKey> {
+        if self.active.kid == kid {
+            return Some(&self.active);
+        }
+        self.valid_previous().find(|key| key.kid == kid)
+    }
+
+    pub fn sign<T: Serialize>(&self, claims: &T) -> Result<String, Error> {
+        let mut header = Header::new(Algorithm::EdDSA);
+        header.kid = Some(self.active.kid.clone());
+        encode(&header, claims, &self.active.encoding_key)
+    }
+
+    pub fn verify<T: DeserializeOwned>(&self, token: &str) -> Result<TokenData<T>, Error> {
+        #[cfg(test)]
+        self.verifications.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        let header = decode_header(token)?;
+        let key = header
+            .kid
+            .as_deref()
+            .and_then(|kid| self.find(kid))
+            .ok_or_else(|| Error::from(ErrorKind::InvalidKeyFormat))?;
+
+        decode::<T>(token, &key.decoding_key, &Validation::new(Algorithm::EdDSA))
+    }
+
+    pub fn jwks(&self) -> JwkSet {
+        let mut keys = vec![self.active.to_jwk()];
+        keys.extend(self.valid_previous().map(SigningKey::to_jwk));
+        JwkSet { keys }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+    use serde::Deserialize;
+
+    #[derive(Serialize, Deserialize)]
+    struct TestClaims {
+        sub: String,
+        exp: usize,
+    }
+
+    fn token(key: SigningKey) -> String {
+        let claims = TestClaims { sub: "user".to_string(), exp: (Utc::now() + Duration::hours(1)).timestamp() as usize };
+        Keyring::new(key, vec![]).sign(&claims).unwrap()
+    }
+
+    #[test]
+    fn previous_key_is_accepted_until_not_after() {
+        let document = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+        let signed = token(SigningKey::from_pkcs8("old", document.as_ref()).unwrap());
+
+        let valid = Keyring::new(
+            SigningKey::generate("new").unwrap(),
+            vec![(SigningKey::from_pkcs8("old", document.as_ref()).unwrap(), Utc::now() + Duration::hours(1))],
+        );
+        assert!(valid.verify::<TestClaims>(&signed).is_ok());
+        assert_eq!(valid.jwks().keys.len(), 2);
+
+        let expired = Keyring::new(
+            SigningKey::generate("new").unwrap(),
+            vec![(SigningKey::from_pkcs8("old", document.as_ref()).unwrap(), Utc::now() - Duration::seconds(1))],
+        );
+        assert!(expired.verify::<TestClaims>(&signed).is_err());
+        assert_eq!(expired.jwks().keys.len(), 1);
+    }
+
+    #[test]
+    fn parses_previous_key_entries() {
+        let (kid, path, not_after) = parse_previous_key("old=keys/old.pem@2026-11-01T00:00:00Z").unwrap();
+        assert_eq!((kid, path), ("old", "keys/old.pem"));
+        assert_eq!(not_after.to_rfc3339(), "2026-11-01T00:00:00+00:00");
+
+        assert!(parse_previous_key("old=keys/old.pem").is_err());
+        assert!(parse_previous_key("keys/old.pem@2026-11-01T00:00:00Z").is_err());
+        assert!(parse_previous_key("old=keys/old.pem@tomorrow").is_err());
+    }
+
+    #[test]
+    fn requires_a_signing_key_unless_ephemeral() {
+        let config = AuthConfig::default();
+        assert!(Keyring::from_config(&config).is_err());
+
+        let config = AuthConfig { ephemeral_signing_key: true, ..AuthConfig::default() };
+        assert!(Keyring::from_config(&config).is_ok());
+    }
+}
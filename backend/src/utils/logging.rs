@@ -0,0 +1,54 @@
+use axum::{body::Body, extract::MatchedPath, http::{Request, Response}};
+use std::time::Duration;
+use tracing::{field::Empty, info_span, Span};
+use tracing_subscriber::EnvFilter;
+
+use crate::utils::config::LoggingConfig;
+
+pub fn init_logging(config: &LoggingConfig) {
+    // RUST_LOG wins over the configured level so a single module can be turned up
+    let filter = EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| EnvFilter::new(&config.level));
+
+    let subscriber = tracing_subscriber::fmt().with_env_filter(filter);
+    if config.format == "json" {
+        subscriber.json().with_current_span(true).with_span_list(false).init();
+    } else {
+        subscriber.init();
+    }
+}
+
+// Span wrapping every request, user_id is filled in by auth_middleware
+pub fn make_request_span(request: &Request<Body>) -> Span {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str())
+        .unwrap_or("unmatched");
+    let request_id = request
+        .headers()
+        .get("x-request-id")
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+
+    info_span!(
+        "request",
+        method = %request.method(),
+        route,
+        request_id,
+        user_id = Empty,
+        status = Empty,
+        latency_ms = Empty,
+    )
+}
+
+pub fn on_response(response: &Response<Body>, latency: Duration, span: &Span) {
+    span.record("status", response.status().as_u16());
+    span.record("latency_ms", latency.as_millis() as u64);
+
+    if response.status().is_server_error() {
+        tracing::error!("request failed");
+    } else {
+        tracing::info!("request finished");
+    }
+}
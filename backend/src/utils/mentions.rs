@@ -0,0 +1,97 @@
+use axum::http::StatusCode;
+use bson::{doc, oid::ObjectId};
+use futures::TryStreamExt;
+use mongodb::Database;
+use std::collections::HashMap;
+
+use crate::middleware::auth_middleware::Claims;
+use crate::models::{mention_model::Mention, notification_model::NotificationKind, task_model::Task, user_model::User};
+use crate::utils::{authorization::{task_allows, Action}, notifications::notify};
+
+// more @usernames than this in one text are left as plain text
+const MAX_MENTIONS: usize = 20;
+
+fn username_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '.' || c == '-'
+}
+
+// The distinct @usernames of a text. An @ inside a word, like in an email
+// address, is not a mention, and a trailing . or - ends the sentence.
+pub fn mentioned_usernames(text: &str) -> Vec<String> {
+    let mut usernames: Vec<String> = Vec::new();
+    let mut previous: Option<char> = None;
+
+    for (i, c) in text.char_indices() {
+        let starts_mention = c == '@' && !previous.is_some_and(|p| username_char(p) || p == '@');
+        previous = Some(c);
+        if !starts_mention {
+            continue;
+        }
+
+        let rest = &text[i + 1..];
+        let end = rest.find(|c: char| !username_char(c)).unwrap_or(rest.len());
+        let username = rest[..end].trim_end_matches(['.', '-']);
+        if !username.is_empty() && !usernames.iter().any(|known| known == username) {
+            usernames.push(username.to_string());
+            if usernames.len() == MAX_MENTIONS {
+                break;
+            }
+        }
+    }
+    usernames
+}
+
+// Resolves the @usernames of a text to the users who can see the task.
+// Unknown usernames, usernames shared by several users and users without
+// access are left out, the text keeps them as plain text.
+pub async fn resolve_mentions(db: &Database, task: &Task, text: &str) -> Result<Vec<Mention>, (StatusCode, String)> {
+    let usernames = mentioned_usernames(text);
+    if usernames.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let users: Vec<User> = match db.collection::<User>("user").find(doc! { "username": { "$in": &usernames } }).await {
+        Ok(cursor) => cursor.try_collect().await,
+        Err(e) => Err(e),
+    }
+    .map_err(|e| {
+        tracing::error!("Error while resolving the mentions: {}", e);
+        (StatusCode::INTERNAL_SERVER_ERROR, "Internal Server Error".to_string())
+    })?;
+
+    let mut by_username: HashMap<&str, Vec<&User>> = HashMap::new();
+    for user in &users {
+        by_username.entry(user.username.as_str()).or_default().push(user);
+    }
+
+    let mut mentions = Vec::new();
+    for username in &usernames {
+        let Some([user]) = by_username.get(username.as_str()).map(Vec::as_slice) else {
+            continue;
+        };
+        let Some(user_id) = user.user_id.map(|id| id.to_hex()) else {
+            continue;
+        };
+        if task_allows(db, task, &user_id, Action::View).await? {
+            mentions.push(Mention { user_id, username: username.clone() });
+        }
+    }
+    Ok(mentions)
+}
+
+// Notifies the users mentioned now that were not mentioned before, except the author
+pub async fn notify_mentions(
+    db: &Database,
+    mentions: &[Mention],
+    before: &[Mention],
+    author: &Claims,
+    task_id: ObjectId,
+    comment_id: Option<ObjectId>,
+) {
+    let recipients = mentions
+        .iter()
+        .filter(|mention| !before.contains(mention))
+        .map(|mention| mention.user_id.clone())
+        .collect();
+    notify(db, NotificationKind::Mentioned, recipients, task_id, comment_id, Some(author)).await;
+}
@@ -0,0 +1,27 @@
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+use mongodb::event::{command::CommandEvent, EventHandler};
+
+// Latency buckets in seconds, shared by the HTTP and the MongoDB histograms
+const LATENCY_BUCKETS: [f64; 12] = [0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0];
+
+pub fn init_metrics() -> Result<PrometheusHandle, String> {
+    PrometheusBuilder::new()
+        .set_buckets_for_metric(Matcher::Suffix("duration_seconds".to_string()), &LATENCY_BUCKETS)
+        .and_then(|builder| builder.install_recorder())
+        .map_err(|e| format!("Failed to install the metrics recorder: {}", e))
+}
+
+// Records the latency of every MongoDB command, passed to the client options in db_connect
+pub fn mongo_command_metrics() -> EventHandler<CommandEvent> {
+    EventHandler::callback(|event: CommandEvent| {
+        let (command, outcome, duration) = match event {
+            CommandEvent::Succeeded(event) => (event.command_name, "success", event.duration),
+            CommandEvent::Failed(event) => (event.command_name, "failure", event.duration),
+            CommandEvent::Started(_) => return,
+            _ => return,
+        };
+
+        metrics::histogram!("mongodb_command_duration_seconds", "command" => command, "outcome" => outcome)
+            .record(duration.as_secs_f64());
+    })
+}
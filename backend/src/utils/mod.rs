@@ -1 +1,18 @@
-pub mod db;
\ No newline at end of file
+pub mod audit;
+pub mod authorization;
+pub mod config;
+pub mod db;
+pub mod events;
+pub mod keyring;
+pub mod logging;
+pub mod mentions;
+pub mod metrics;
+pub mod notifications;
+pub mod oidc;
+pub mod password;
+pub mod rate_limit;
+pub mod server;
+pub mod shutdown;
+pub mod state;
+pub mod trash;
+pub mod webhooks;
\ No newline at end of file
@@ -0,0 +1,113 @@
+use bson::{doc, oid::ObjectId, DateTime};
+use futures::TryStreamExt;
+use mongodb::Database;
+use std::time::Duration;
+
+use crate::middleware::auth_middleware::Claims;
+use crate::models::{
+    notification_model::{Notification, NotificationKind}, task_model::{not_deleted, Task}, user_model::User
+};
+use crate::utils::config::NotificationsConfig;
+
+// The users following a task: its creator and its assignees
+pub fn watchers(task: &Task) -> Vec<String> {
+    std::iter::once(task.user_id.clone()).chain(task.assignee_ids.iter().cloned()).collect()
+}
+
+// Notifies the recipients of a task event, except the one who caused it and
+// the ones who turned this kind off. A failure is only logged, the change
+// that caused the event has already been made.
+pub async fn notify(
+    db: &Database,
+    kind: NotificationKind,
+    recipients: Vec<String>,
+    task_id: ObjectId,
+    comment_id: Option<ObjectId>,
+    actor: Option<&Claims>,
+) {
+    let mut recipient_ids: Vec<ObjectId> = recipients
+        .iter()
+        .filter(|user_id| actor.is_none_or(|actor| actor.user_id != **user_id))
+        .filter_map(|user_id| ObjectId::parse_str(user_id).ok())
+        .collect();
+    recipient_ids.sort();
+    recipient_ids.dedup();
+    if recipient_ids.is_empty() {
+        return;
+    }
+
+    let users: Vec<User> = match db.collection::<User>("user").find(doc! { "_id": { "$in": &recipient_ids } }).await {
+        Ok(cursor) => match cursor.try_collect().await {
+            Ok(users) => users,
+            Err(e) => {
+                tracing::error!("Failed to load the users to notify: {}", e);
+                return;
+            }
+        },
+        Err(e) => {
+            tracing::error!("Failed to load the users to notify: {}", e);
+            return;
+        }
+    };
+
+    let created_at = DateTime::now();
+    let notifications: Vec<Notification> = users
+        .into_iter()
+        .filter(|user| user.notification_preferences.allows(kind))
+        .filter_map(|user| user.user_id)
+        .map(|user_id| Notification {
+            notification_id: None,
+            user_id: user_id.to_hex(),
+            kind,
+            task_id,
+            comment_id,
+            actor_id: actor.map(|actor| actor.user_id.clone()),
+            actor_name: actor.map(|actor| actor.username.clone()),
+            created_at,
+            read: false,
+        })
+        .collect();
+    if notifications.is_empty() {
+        return;
+    }
+
+    if let Err(e) = db.collection::<Notification>("notification").insert_many(notifications).await {
+        tracing::error!("Failed to save the notifications: {}", e);
+    }
+}
+
+// Periodically notifies the watchers of tasks that become due within the
+// configured window, once per due date
+pub async fn due_soon_sweeper(db: Database, config: NotificationsConfig) {
+    let mut interval = tokio::time::interval(Duration::from_secs(config.due_sweep_interval_secs));
+    let window_ms = (config.due_soon_hours as i64).saturating_mul(60 * 60 * 1000);
+
+    loop {
+        interval.tick().await;
+        if let Err(e) = notify_due_soon(&db, window_ms).await {
+            tracing::error!("Failed to look for tasks due soon: {}", e);
+        }
+    }
+}
+
+async fn notify_due_soon(db: &Database, window_ms: i64) -> Result<(), mongodb::error::Error> {
+    let now = DateTime::now();
+    let until = DateTime::from_millis(now.timestamp_millis().saturating_add(window_ms));
+    let collection = db.collection::<Task>("task");
+
+    let tasks: Vec<Task> = collection
+        .find(not_deleted(doc! { "due_at": { "$gt": now, "$lte": until }, "due_notified": { "$ne": true } }))
+        .await?
+        .try_collect()
+        .await?;
+
+    for task in tasks {
+        let Some(task_id) = task.task_id else { continue };
+        // marked first so a failing insert doesn't notify twice
+        collection
+            .update_one(doc! { "_id": task_id }, doc! { "$set": { "due_notified": true } })
+            .await?;
+        notify(db, NotificationKind::DueSoon, watchers(&task), task_id, None, None).await;
+    }
+    Ok(())
+}
@@ -0,0 +1,419 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use bson::doc;
+use jsonwebtoken::{
+    decode, decode_header,
+    jwk::{AlgorithmParameters, EllipticCurve, Jwk, JwkSet, KeyAlgorithm},
+    Algorithm, DecodingKey, Validation,
+};
+use mongodb::{options::IndexOptions, Collection, Database, IndexModel};
+use reqwest::Url;
+use ring::{digest, rand::{SecureRandom, SystemRandom}};
+use serde::{Deserialize, Serialize};
+use std::{sync::Mutex, time::{Duration, Instant}};
+use tokio::sync::OnceCell;
+
+use crate::utils::config::OidcConfig;
+
+// How long a user has to finish the login at the identity provider
+const PENDING_LOGIN_TTL: i64 = 10; // minutes
+// How long the provider keys are reused before being fetched again
+const JWKS_TTL: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Deserialize, Clone)]
+pub struct ProviderMetadata {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub jwks_uri: String,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+// The claims of the id_token we care about
+#[derive(Deserialize)]
+pub struct IdTokenClaims {
+    pub sub: String,
+    pub email: Option<String>,
+    #[serde(default)]
+    pub email_verified: bool,
+    pub nonce: Option<String>,
+    pub preferred_username: Option<String>,
+}
+
+// Kept in MongoDB between the redirect and the callback, so any replica can
+// complete the login
+#[derive(Serialize, Deserialize)]
+struct PendingLogin {
+    #[serde(rename = "_id")]
+    state: String,
+    nonce: String,
+    code_verifier: String,
+    // the scopes of the token issued at the callback
+    #[serde(default)]
+    scopes: Vec<String>,
+    created_at: bson::DateTime,
+}
+
+// Authorization code + PKCE flow against a single OpenID Connect provider
+pub struct OidcClient {
+    issuer: String,
+    client_id: String,
+    client_secret: Option<String>,
+    redirect_uri: String,
+    http: reqwest::Client,
+    metadata: OnceCell<ProviderMetadata>,
+    jwks: Mutex<Option<(Instant, JwkSet)>>,
+}
+
+fn pending_logins(db: &Database) -> Collection<PendingLogin> {
+    db.collection("oidc_login")
+}
+
+// Expires the abandoned logins, a login older than the TTL is also refused
+// before MongoDB gets to delete it
+pub async fn create_indexes(db: &Database) -> Result<(), String> {
+    let index = IndexModel::builder()
+        .keys(doc! { "created_at": 1 })
+        .options(IndexOptions::builder().expire_after(Duration::from_secs(PENDING_LOGIN_TTL as u64 * 60)).build())
+        .build();
+    pending_logins(db)
+        .create_index(index)
+        .await
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+// The algorithm the provider key is for, the token header is not trusted for it
+fn jwk_algorithm(jwk: &Jwk) -> Option<Algorithm> {
+    match (&jwk.algorithm, jwk.common.key_algorithm) {
+        (AlgorithmParameters::RSA(_), None | Some(KeyAlgorithm::RS256)) => Some(Algorithm::RS256),
+        (AlgorithmParameters::RSA(_), Some(KeyAlgorithm::RS384)) => Some(Algorithm::RS384),
+        (AlgorithmParameters::RSA(_), Some(KeyAlgorithm::RS512)) => Some(Algorithm::RS512),
+        (AlgorithmParameters::RSA(_), Some(KeyAlgorithm::PS256)) => Some(Algorithm::PS256),
+        (AlgorithmParameters::RSA(_), Some(KeyAlgorithm::PS384)) => Some(Algorithm::PS384),
+        (AlgorithmParameters::RSA(_), Some(KeyAlgorithm::PS512)) => Some(Algorithm::PS512),
+        (AlgorithmParameters::EllipticCurve(params), None | Some(KeyAlgorithm::ES256))
+            if params.curve == EllipticCurve::P256 => Some(Algorithm::ES256),
+        (AlgorithmParameters::EllipticCurve(params), None | Some(KeyAlgorithm::ES384))
+            if params.curve == EllipticCurve::P384 => Some(Algorithm::ES384),
+        (AlgorithmParameters::OctetKeyPair(params), None | Some(KeyAlgorithm::EdDSA))
+            if params.curve == EllipticCurve::Ed25519 => Some(Algorithm::EdDSA),
+        // symmetric keys and mismatched declarations
+        _ => None,
+    }
+}
+
+fn random_token() -> Result<String, String> {
+    let mut bytes = [0u8; 32];
+    SystemRandom::new()
+        .fill(&mut bytes)
+        .map_err(|_| "Failed to generate random bytes".to_string())?;
+    Ok(URL_SAFE_NO_PAD.encode(bytes))
+}
+
+impl OidcClient {
+    pub fn new(config: &OidcConfig) -> Self {
+        OidcClient {
+            issuer: config.issuer.trim_end_matches('/').to_string(),
+            client_id: config.client_id.clone(),
+            client_secret: config.client_secret.clone(),
+            redirect_uri: config.redirect_uri.clone(),
+            http: reqwest::Client::new(),
+            metadata: OnceCell::new(),
+            jwks: Mutex::new(None),
+        }
+    }
+
+    // Discovery document, fetched on first use so the app can start while the provider is down
+    async fn metadata(&self) -> Result<&ProviderMetadata, String> {
+        self.metadata
+            .get_or_try_init(|| async {
+                let url = format!("{}/.well-known/openid-configuration", self.issuer);
+                let metadata: ProviderMetadata = self.http.get(&url).send().await
+                    .and_then(|res| res.error_for_status())
+                    .map_err(|e| format!("Failed to fetch {}: {}", url, e))?
+                    .json().await
+                    .map_err(|e| format!("Invalid discovery document: {}", e))?;
+
+                if metadata.issuer.trim_end_matches('/') != self.issuer {
+                    return Err(format!("Discovery document issuer {} does not match {}", metadata.issuer, self.issuer));
+                }
+                Ok(metadata)
+            })
+            .await
+    }
+
+    // Url the browser is sent to, the state/nonce/verifier are kept until the callback
+    pub async fn authorization_url(&self, db: &Database, scopes: Vec<String>) -> Result<String, String> {
+        let metadata = self.metadata().await?;
+
+        let state = random_token()?;
+        let nonce = random_token()?;
+        let code_verifier = random_token()?;
+        let code_challenge = URL_SAFE_NO_PAD.encode(digest::digest(&digest::SHA256, code_verifier.as_bytes()));
+
+        let url = Url::parse_with_params(&metadata.authorization_endpoint, &[
+            ("response_type", "code"),
+            ("client_id", self.client_id.as_str()),
+            ("redirect_uri", self.redirect_uri.as_str()),
+            ("scope", "openid email profile"),
+            ("state", state.as_str()),
+            ("nonce", nonce.as_str()),
+            ("code_challenge", code_challenge.as_str()),
+            ("code_challenge_method", "S256"),
+        ]).map_err(|e| format!("Invalid authorization endpoint: {}", e))?;
+
+        let pending = PendingLogin { state, nonce, code_verifier, scopes, created_at: bson::DateTime::now() };
+        pending_logins(db)
+            .insert_one(pending)
+            .await
+            .map_err(|e| format!("Failed to save the login state: {}", e))?;
+
+        Ok(url.to_string())
+    }
+
+    // Exchanges the code for an id_token and returns its verified claims with
+    // the scopes asked for when the login started, a state can only be used once
+    pub async fn exchange(&self, db: &Database, code: &str, state: &str) -> Result<(IdTokenClaims, Vec<String>), String> {
+        let cutoff = bson::DateTime::from_millis(
+            bson::DateTime::now().timestamp_millis() - PENDING_LOGIN_TTL * 60 * 1000,
+        );
+        let pending = pending_logins(db)
+            .find_one_and_delete(doc! { "_id": state, "created_at": { "$gt": cutoff } })
+            .await
+            .map_err(|e| format!("Failed to load the login state: {}", e))?
+            .ok_or_else(|| "Unknown or expired login state".to_string())?;
+
+        let claims = self.redeem(code, &pending).await?;
+        Ok((claims, pending.scopes))
+    }
+
+    async fn redeem(&self, code: &str, pending: &PendingLogin) -> Result<IdTokenClaims, String> {
+        let metadata = self.metadata().await?;
+
+        let mut form = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", self.redirect_uri.as_str()),
+            ("client_id", self.client_id.as_str()),
+            ("code_verifier", pending.code_verifier.as_str()),
+        ];
+        if let Some(secret) = &self.client_secret {
+            form.push(("client_secret", secret.as_str()));
+        }
+
+        let tokens: TokenResponse = self.http.post(&metadata.token_endpoint).form(&form).send().await
+            .and_then(|res| res.error_for_status())
+            .map_err(|e| format!("Token request failed: {}", e))?
+            .json().await
+            .map_err(|e| format!("Invalid token response: {}", e))?;
+
+        let claims = self.verify_id_token(metadata, &tokens.id_token).await?;
+        if claims.nonce.as_deref() != Some(pending.nonce.as_str()) {
+            return Err("The id_token nonce does not match".to_string());
+        }
+        Ok(claims)
+    }
+
+    // The provider key with this kid from the cache, None when it is stale or
+    // doesn't have the key
+    fn cached_key(&self, kid: &str) -> Option<Jwk> {
+        let jwks = self.jwks.lock().unwrap();
+        let (fetched_at, jwks) = jwks.as_ref()?;
+        if fetched_at.elapsed() > JWKS_TTL {
+            return None;
+        }
+        jwks.find(kid).cloned()
+    }
+
+    async fn provider_key(&self, metadata: &ProviderMetadata, kid: &str) -> Result<Jwk, String> {
+        if let Some(jwk) = self.cached_key(kid) {
+            return Ok(jwk);
+        }
+
+        // a kid missing from a fresh set may be a key the provider just rotated in
+        let jwks: JwkSet = self.http.get(&metadata.jwks_uri).send().await
+            .and_then(|res| res.error_for_status())
+            .map_err(|e| format!("Failed to fetch the provider keys: {}", e))?
+            .json().await
+            .map_err(|e| format!("Invalid provider keys: {}", e))?;
+
+        let jwk = jwks.find(kid).cloned();
+        *self.jwks.lock().unwrap() = Some((Instant::now(), jwks));
+        jwk.ok_or_else(|| "No provider key matches the id_token".to_string())
+    }
+
+    async fn verify_id_token(&self, metadata: &ProviderMetadata, id_token: &str) -> Result<IdTokenClaims, String> {
+        let header = decode_header(id_token).map_err(|e| format!("Invalid id_token: {}", e))?;
+        let kid = header.kid.as_deref().ok_or_else(|| "The id_token has no kid".to_string())?;
+
+        let jwk = self.provider_key(metadata, kid).await?;
+        let algorithm = jwk_algorithm(&jwk).ok_or_else(|| format!("Unsupported provider key {}", kid))?;
+        if header.alg != algorithm {
+            return Err(format!("The id_token is signed with {:?} but key {} is for {:?}", header.alg, kid, algorithm));
+        }
+        let key = DecodingKey::from_jwk(&jwk).map_err(|e| format!("Unsupported provider key: {}", e))?;
+
+        let mut validation = Validation::new(algorithm);
+        validation.set_audience(&[&self.client_id]);
+        validation.set_issuer(&[&metadata.issuer]);
+
+        decode::<IdTokenClaims>(id_token, &key, &validation)
+            .map(|data| data.claims)
+            .map_err(|e| format!("id_token verification failed: {}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{extract::State, routing::{get, post}, Json, Router};
+    use jsonwebtoken::{encode, EncodingKey, Header};
+    use ring::signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING};
+    use serde_json::{json, Value};
+    use std::sync::{atomic::{AtomicUsize, Ordering}, Arc};
+
+    // A local identity provider returning whatever id_token the test put in
+    #[derive(Clone)]
+    struct MockProvider {
+        issuer: String,
+        jwks: Value,
+        id_token: Arc<Mutex<String>>,
+        jwks_fetches: Arc<AtomicUsize>,
+    }
+
+    async fn mock_provider(jwks: Value) -> MockProvider {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let provider = MockProvider {
+            issuer: format!("http://{}", listener.local_addr().unwrap()),
+            jwks,
+            id_token: Arc::new(Mutex::new(String::new())),
+            jwks_fetches: Arc::new(AtomicUsize::new(0)),
+        };
+
+        let app = Router::new()
+            .route("/.well-known/openid-configuration", get(|State(provider): State<MockProvider>| async move {
+                Json(json!({
+                    "issuer": provider.issuer,
+                    "authorization_endpoint": format!("{}/authorize", provider.issuer),
+                    "token_endpoint": format!("{}/token", provider.issuer),
+                    "jwks_uri": format!("{}/jwks", provider.issuer),
+                }))
+            }))
+            .route("/token", post(|State(provider): State<MockProvider>| async move {
+                Json(json!({ "id_token": provider.id_token.lock().unwrap().clone() }))
+            }))
+            .route("/jwks", get(|State(provider): State<MockProvider>| async move {
+                provider.jwks_fetches.fetch_add(1, Ordering::SeqCst);
+                Json(provider.jwks.clone())
+            }))
+            .with_state(provider.clone());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        provider
+    }
+
+    // An ES256 provider key, as the encoding key and its public JWK
+    fn provider_key(kid: &str) -> (EncodingKey, Value) {
+        let rng = SystemRandom::new();
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng).unwrap();
+        let key_pair = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref(), &rng).unwrap();
+        // uncompressed point, 0x04 then x and y
+        let point = key_pair.public_key().as_ref();
+        let jwk = json!({
+            "kty": "EC",
+            "crv": "P-256",
+            "kid": kid,
+            "use": "sig",
+            "x": URL_SAFE_NO_PAD.encode(&point[1..33]),
+            "y": URL_SAFE_NO_PAD.encode(&point[33..65]),
+        });
+        (EncodingKey::from_ec_der(pkcs8.as_ref()), jwk)
+    }
+
+    fn id_token(provider: &MockProvider, header: Header, key: &EncodingKey, nonce: &str) -> String {
+        let claims = json!({
+            "iss": provider.issuer,
+            "aud": "backend",
+            "sub": "provider-user",
+            "email": "sso@example.com",
+            "email_verified": true,
+            "nonce": nonce,
+            "exp": chrono::Utc::now().timestamp() + 300,
+        });
+        encode(&header, &claims, key).unwrap()
+    }
+
+    fn es256(kid: Option<&str>) -> Header {
+        let mut header = Header::new(Algorithm::ES256);
+        header.kid = kid.map(str::to_string);
+        header
+    }
+
+    fn pending() -> PendingLogin {
+        PendingLogin {
+            state: "state".to_string(),
+            nonce: "nonce".to_string(),
+            code_verifier: "verifier".to_string(),
+            scopes: vec![],
+            created_at: bson::DateTime::now(),
+        }
+    }
+
+    fn client(provider: &MockProvider) -> OidcClient {
+        let _ = rustls::crypto::ring::default_provider().install_default();
+        OidcClient::new(&OidcConfig {
+            issuer: provider.issuer.clone(),
+            client_id: "backend".to_string(),
+            client_secret: None,
+            redirect_uri: "http://localhost/callback".to_string(),
+        })
+    }
+
+    #[tokio::test]
+    async fn verifies_the_id_token_and_caches_the_keys() {
+        let (key, jwk) = provider_key("k1");
+        let provider = mock_provider(json!({ "keys": [jwk] })).await;
+        let client = client(&provider);
+
+        *provider.id_token.lock().unwrap() = id_token(&provider, es256(Some("k1")), &key, "nonce");
+        let claims = client.redeem("code", &pending()).await.unwrap();
+        assert_eq!(claims.sub, "provider-user");
+        assert_eq!(claims.email.as_deref(), Some("sso@example.com"));
+
+        client.redeem("code", &pending()).await.unwrap();
+        assert_eq!(provider.jwks_fetches.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn rejects_a_wrong_nonce_or_a_missing_kid() {
+        let (key, jwk) = provider_key("k1");
+        let provider = mock_provider(json!({ "keys": [jwk] })).await;
+        let client = client(&provider);
+
+        *provider.id_token.lock().unwrap() = id_token(&provider, es256(Some("k1")), &key, "other");
+        assert!(client.redeem("code", &pending()).await.is_err());
+
+        *provider.id_token.lock().unwrap() = id_token(&provider, es256(None), &key, "nonce");
+        let error = client.redeem("code", &pending()).await.err().unwrap();
+        assert!(error.contains("no kid"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn rejects_an_algorithm_the_key_is_not_for() {
+        let (_, jwk) = provider_key("k1");
+        let provider = mock_provider(json!({ "keys": [jwk] })).await;
+        let client = client(&provider);
+
+        // an HMAC token naming the EC key must not be checked with HS256
+        let mut header = Header::new(Algorithm::HS256);
+        header.kid = Some("k1".to_string());
+        let forged = id_token(&provider, header, &EncodingKey::from_secret(b"secret"), "nonce");
+        *provider.id_token.lock().unwrap() = forged;
+
+        let error = client.redeem("code", &pending()).await.err().unwrap();
+        assert!(error.contains("is signed with HS256"), "{}", error);
+    }
+}
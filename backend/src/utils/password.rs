@@ -0,0 +1,170 @@
+use argon2::{
+    password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Algorithm, Argon2, Params, Version,
+};
+use ring::rand::{SecureRandom, SystemRandom};
+use std::{collections::HashSet, fs, sync::Arc};
+
+use crate::utils::config::PasswordConfig;
+
+pub struct VerifyResult {
+    pub valid: bool,
+    // the stored hash is bcrypt or uses older Argon2 parameters
+    pub needs_rehash: bool,
+}
+
+// Argon2id hashing (PHC strings) plus the password policy checked at registration
+pub struct Passwords {
+    params: Params,
+    min_length: usize,
+    breached: HashSet<String>,
+}
+
+impl Passwords {
+    pub fn new(params: Params, min_length: usize, breached: HashSet<String>) -> Self {
+        Passwords { params, min_length, breached }
+    }
+
+    pub fn from_config(config: &PasswordConfig) -> Result<Self, String> {
+        let params = Params::new(
+            config.argon2_memory_kib,
+            config.argon2_iterations,
+            config.argon2_parallelism,
+            None,
+        ).map_err(|e| format!("Invalid Argon2 parameters: {}", e))?;
+
+        let breached = match &config.breached_passwords_file {
+            Some(path) => fs::read_to_string(path)
+                .map_err(|e| format!("Failed to read {}: {}", path, e))?
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty())
+                .map(str::to_string)
+                .collect(),
+            None => HashSet::new(),
+        };
+
+        Ok(Passwords::new(params, config.min_length, breached))
+    }
+
+    fn argon2(&self) -> Argon2<'_> {
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, self.params.clone())
+    }
+
+    pub fn check_policy(&self, password: &str) -> Result<(), String> {
+        if password.chars().count() < self.min_length {
+            return Err(format!("The password must be at least {} characters long", self.min_length));
+        }
+        if self.breached.contains(password) {
+            return Err("The password appears in a list of breached passwords".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn hash(&self, password: &str) -> Result<String, String> {
+        let mut salt = [0u8; 16];
+        SystemRandom::new().fill(&mut salt).map_err(|_| "Failed to generate a salt".to_string())?;
+        let salt = SaltString::encode_b64(&salt).map_err(|e| e.to_string())?;
+
+        self.argon2()
+            .hash_password(password.as_bytes(), &salt)
+            .map(|hash| hash.to_string())
+            .map_err(|e| e.to_string())
+    }
+
+    // Argon2 and bcrypt keep a core busy for tens of milliseconds, the handlers
+    // run them on the blocking pool so they don't stall a runtime worker
+    pub async fn spawn_hash(self: &Arc<Self>, password: &str) -> Result<String, String> {
+        let (passwords, password) = (self.clone(), password.to_string());
+        tokio::task::spawn_blocking(move || passwords.hash(&password))
+            .await
+            .map_err(|e| e.to_string())?
+    }
+
+    pub async fn spawn_verify(self: &Arc<Self>, password: &str, stored: &str) -> Result<VerifyResult, String> {
+        let (passwords, password, stored) = (self.clone(), password.to_string(), stored.to_string());
+        tokio::task::spawn_blocking(move || passwords.verify(&password, &stored))
+            .await
+            .map_err(|e| e.to_string())?
+    }
+
+    pub fn verify(&self, password: &str, stored: &str) -> Result<VerifyResult, String> {
+        // legacy hashes created with bcrypt::DEFAULT_COST
+        if stored.starts_with("$2") {
+            let valid = bcrypt::verify(password, stored).map_err(|e| e.to_string())?;
+            return Ok(VerifyResult { valid, needs_rehash: true });
+        }
+
+        let parsed = PasswordHash::new(stored).map_err(|e| e.to_string())?;
+        let valid = match self.argon2().verify_password(password.as_bytes(), &parsed) {
+            Ok(()) => true,
+            Err(argon2::password_hash::Error::Password) => false,
+            Err(e) => return Err(e.to_string()),
+        };
+
+        let needs_rehash = parsed.algorithm != Algorithm::Argon2id.ident()
+            || Params::try_from(&parsed)
+                .map(|params| {
+                    params.m_cost() != self.params.m_cost()
+                        || params.t_cost() != self.params.t_cost()
+                        || params.p_cost() != self.params.p_cost()
+                })
+                .unwrap_or(true);
+
+        Ok(VerifyResult { valid, needs_rehash })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // small parameters keep the tests fast
+    fn with_memory(memory_kib: u32) -> Passwords {
+        let breached = HashSet::from(["password123".to_string()]);
+        Passwords::new(Params::new(memory_kib, 1, 1, None).unwrap(), 10, breached)
+    }
+
+    #[test]
+    fn checks_length_and_breached_list() {
+        let passwords = with_memory(64);
+        assert!(passwords.check_policy("short").is_err());
+        assert!(passwords.check_policy("password123").is_err());
+        assert!(passwords.check_policy("long enough passphrase").is_ok());
+        // characters, not bytes
+        assert!(passwords.check_policy("ééééééééé").is_err());
+    }
+
+    #[test]
+    fn verifies_its_own_hashes() {
+        let passwords = with_memory(64);
+        let hash = passwords.hash("correct horse").unwrap();
+        assert!(hash.starts_with("$argon2id$"));
+
+        let result = passwords.verify("correct horse", &hash).unwrap();
+        assert!(result.valid && !result.needs_rehash);
+        assert!(!passwords.verify("wrong horse", &hash).unwrap().valid);
+        assert!(passwords.verify("correct horse", "not a hash").is_err());
+    }
+
+    #[test]
+    fn upgrades_bcrypt_and_outdated_argon2_hashes() {
+        let passwords = with_memory(64);
+
+        let legacy = bcrypt::hash("correct horse", 4).unwrap();
+        let result = passwords.verify("correct horse", &legacy).unwrap();
+        assert!(result.valid && result.needs_rehash);
+        assert!(!passwords.verify("wrong horse", &legacy).unwrap().valid);
+
+        let outdated = with_memory(32).hash("correct horse").unwrap();
+        let result = passwords.verify("correct horse", &outdated).unwrap();
+        assert!(result.valid && result.needs_rehash);
+    }
+
+    #[tokio::test]
+    async fn hashes_on_the_blocking_pool() {
+        let passwords = Arc::new(with_memory(64));
+        let hash = passwords.spawn_hash("correct horse").await.unwrap();
+        assert!(passwords.spawn_verify("correct horse", &hash).await.unwrap().valid);
+    }
+}
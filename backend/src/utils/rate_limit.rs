@@ -0,0 +1,207 @@
+use futures::future::BoxFuture;
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use crate::utils::config::{RateLimitConfig, RouteLimit};
+
+// Hard cap on the keys the in-memory store tracks, the least recently used
+// bucket is dropped to make room
+const MAX_TRACKED_KEYS: usize = 10_000;
+
+pub struct Decision {
+    pub allowed: bool,
+    pub limit: u32,
+    pub remaining: u32,
+    // seconds until the bucket is full again
+    pub reset_secs: u64,
+    // seconds until the next request is allowed, 0 when allowed
+    pub retry_after_secs: u64,
+}
+
+// Where the token buckets live. The in-memory store is per process, a shared
+// backend (e.g. Redis) can implement this to limit across replicas.
+pub trait RateLimitStore: Send + Sync {
+    fn take(&self, key: String, limit: RouteLimit) -> BoxFuture<'_, Decision>;
+}
+
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+    // the limit the bucket was last used with, other routes have other limits
+    limit: RouteLimit,
+    // position in InMemoryStore::recent
+    last_use: u64,
+}
+
+impl Bucket {
+    fn per_second(&self) -> f64 {
+        f64::from(self.limit.requests_per_minute) / 60.0
+    }
+
+    fn tokens_at(&self, now: Instant) -> f64 {
+        let refilled = self.tokens + now.duration_since(self.updated_at).as_secs_f64() * self.per_second();
+        refilled.min(f64::from(self.limit.burst))
+    }
+}
+
+#[derive(Default)]
+struct Buckets {
+    by_key: HashMap<String, Bucket>,
+    // keys by last use, oldest first
+    recent: BTreeMap<u64, String>,
+    next_use: u64,
+}
+
+impl Buckets {
+    // Drops the buckets that have refilled, they are the same as a new one, and
+    // then the least recently used ones down to the cap
+    fn make_room(&mut self, now: Instant) {
+        while let Some(entry) = self.recent.first_entry() {
+            let full = self.by_key.get(entry.get()).is_none_or(|bucket| {
+                bucket.tokens_at(now) >= f64::from(bucket.limit.burst)
+            });
+            if !full && self.by_key.len() < MAX_TRACKED_KEYS {
+                break;
+            }
+            let key = entry.remove();
+            self.by_key.remove(&key);
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct InMemoryStore {
+    buckets: Mutex<Buckets>,
+}
+
+impl InMemoryStore {
+    fn take_now(&self, key: String, limit: RouteLimit) -> Decision {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+        if !buckets.by_key.contains_key(&key) {
+            buckets.make_room(now);
+        }
+
+        let last_use = buckets.next_use;
+        buckets.next_use += 1;
+        let bucket = buckets.by_key.entry(key.clone()).or_insert(Bucket {
+            tokens: f64::from(limit.burst),
+            updated_at: now,
+            limit,
+            last_use,
+        });
+        let previous_use = std::mem::replace(&mut bucket.last_use, last_use);
+        bucket.tokens = bucket.tokens_at(now);
+        bucket.updated_at = now;
+
+        let capacity = f64::from(limit.burst);
+        let per_second = bucket.per_second();
+        let allowed = bucket.tokens >= 1.0;
+        if allowed {
+            bucket.tokens -= 1.0;
+        }
+
+        let decision = Decision {
+            allowed,
+            limit: limit.burst,
+            remaining: bucket.tokens.floor() as u32,
+            reset_secs: ((capacity - bucket.tokens) / per_second).ceil() as u64,
+            retry_after_secs: if allowed { 0 } else { ((1.0 - bucket.tokens) / per_second).ceil() as u64 },
+        };
+
+        buckets.recent.remove(&previous_use);
+        buckets.recent.insert(last_use, key);
+        decision
+    }
+}
+
+impl RateLimitStore for InMemoryStore {
+    fn take(&self, key: String, limit: RouteLimit) -> BoxFuture<'_, Decision> {
+        let decision = self.take_now(key, limit);
+        Box::pin(async move { decision })
+    }
+}
+
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    store: Arc<dyn RateLimitStore>,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> Self {
+        Self::with_store(config, Arc::new(InMemoryStore::default()))
+    }
+
+    pub fn with_store(config: RateLimitConfig, store: Arc<dyn RateLimitStore>) -> Self {
+        RateLimiter { config, store }
+    }
+
+    pub fn trust_forwarded_for(&self) -> bool {
+        self.config.trust_forwarded_for
+    }
+
+    // None when rate limiting is disabled
+    pub async fn check(&self, method: &str, route: &str, subject: &str) -> Option<Decision> {
+        if !self.config.enabled {
+            return None;
+        }
+
+        let limit = self.config.limit_for(method, route);
+        Some(self.store.take(format!("{} {}|{}", method, route, subject), limit).await)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STRICT: RouteLimit = RouteLimit { requests_per_minute: 1, burst: 2 };
+
+    #[tokio::test]
+    async fn keeps_a_bucket_per_method_and_route() {
+        let config = RateLimitConfig::default();
+        assert_eq!(config.limit_for("POST", "/api/v1/tasks").burst, 10);
+        assert_eq!(config.limit_for("GET", "/api/v1/tasks").burst, config.default.burst);
+
+        let limiter = RateLimiter::with_store(
+            RateLimitConfig { routes: HashMap::from([("POST /tasks".to_string(), STRICT)]), ..config },
+            Arc::new(InMemoryStore::default()),
+        );
+        for _ in 0..2 {
+            assert!(limiter.check("POST", "/tasks", "user:1").await.unwrap().allowed);
+        }
+        assert!(!limiter.check("POST", "/tasks", "user:1").await.unwrap().allowed);
+        assert!(limiter.check("GET", "/tasks", "user:1").await.unwrap().allowed);
+    }
+
+    #[test]
+    fn never_tracks_more_than_the_cap() {
+        let store = InMemoryStore::default();
+        store.take_now("oldest".to_string(), STRICT);
+        for i in 0..MAX_TRACKED_KEYS + 100 {
+            store.take_now(format!("ip:{}", i), STRICT);
+        }
+
+        let buckets = store.buckets.lock().unwrap();
+        assert_eq!(buckets.by_key.len(), MAX_TRACKED_KEYS);
+        assert_eq!(buckets.recent.len(), MAX_TRACKED_KEYS);
+        assert!(!buckets.by_key.contains_key("oldest"));
+    }
+
+    #[test]
+    fn drops_refilled_buckets_first() {
+        let store = InMemoryStore::default();
+        let fast = RouteLimit { requests_per_minute: 6_000_000, burst: 1 };
+        store.take_now("refilled".to_string(), fast);
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        store.take_now("empty".to_string(), STRICT);
+        store.take_now("new".to_string(), STRICT);
+
+        let buckets = store.buckets.lock().unwrap();
+        assert!(!buckets.by_key.contains_key("refilled"));
+        assert!(buckets.by_key.contains_key("empty"));
+    }
+}
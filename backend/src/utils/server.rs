@@ -0,0 +1,145 @@
+use axum::Router;
+use axum_server::{tls_rustls::RustlsConfig, Handle};
+use std::{
+    fs,
+    net::SocketAddr,
+    sync::{atomic::AtomicBool, Arc},
+    time::{Duration, SystemTime},
+};
+use tokio::sync::Notify;
+
+use crate::utils::{
+    config::{ServerConfig, TlsConfig},
+    shutdown::shutdown_signal,
+};
+
+// Serves the app on the configured unix socket, TLS or plain TCP listener until
+// shutdown. Returns false when the drain timeout cut in-flight requests.
+pub async fn run_server(
+    config: &ServerConfig,
+    tls: Option<&TlsConfig>,
+    app: Router,
+    draining: Arc<AtomicBool>,
+) -> Result<bool, String> {
+    let readiness_delay = Duration::from_millis(config.readiness_delay_ms);
+    let drain_timeout = Duration::from_millis(config.drain_timeout_ms);
+    let shutdown_started = Arc::new(Notify::new());
+    let signal = shutdown_signal(draining, readiness_delay, shutdown_started.clone());
+
+    let server = async {
+        if let Some(path) = &config.unix_socket {
+            serve_unix(path, app, signal).await
+        } else if let Some(tls) = tls {
+            serve_tls(config, tls, app, signal).await
+        } else {
+            let addr = config.bind_addr();
+            let listener = tokio::net::TcpListener::bind(addr)
+                .await
+                .map_err(|e| format!("Failed to bind {}: {}", addr, e))?;
+            tracing::info!("The server is up on address: {}", addr);
+
+            axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
+                .with_graceful_shutdown(signal)
+                .await
+                .map_err(|e| e.to_string())
+        }
+    };
+
+    tokio::select! {
+        result = server => result.map(|_| true),
+        _ = async {
+            shutdown_started.notified().await;
+            tokio::time::sleep(drain_timeout).await;
+        } => {
+            tracing::warn!("Drain timeout of {:?} reached, closing the remaining connections", drain_timeout);
+            Ok(false)
+        }
+    }
+}
+
+#[cfg(unix)]
+async fn serve_unix(path: &str, app: Router, signal: impl Future<Output = ()> + Send + 'static) -> Result<(), String> {
+    // a socket left behind by a previous run would make bind fail, anything
+    // else at that path is a configuration mistake and is left alone
+    if let Ok(metadata) = fs::symlink_metadata(path) {
+        use std::os::unix::fs::FileTypeExt;
+        if !metadata.file_type().is_socket() {
+            return Err(format!("{} exists and is not a unix socket", path));
+        }
+        fs::remove_file(path).map_err(|e| format!("Failed to remove the stale socket {}: {}", path, e))?;
+    }
+    let listener = tokio::net::UnixListener::bind(path)
+        .map_err(|e| format!("Failed to bind the unix socket {}: {}", path, e))?;
+    tracing::info!("The server is up on unix socket: {}", path);
+
+    let result = axum::serve(listener, app)
+        .with_graceful_shutdown(signal)
+        .await
+        .map_err(|e| e.to_string());
+
+    let _ = fs::remove_file(path);
+    result
+}
+
+#[cfg(not(unix))]
+async fn serve_unix(_path: &str, _app: Router, _signal: impl Future<Output = ()> + Send + 'static) -> Result<(), String> {
+    Err("Unix sockets are not supported on this platform".to_string())
+}
+
+async fn serve_tls(
+    config: &ServerConfig,
+    tls: &TlsConfig,
+    app: Router,
+    signal: impl Future<Output = ()> + Send + 'static,
+) -> Result<(), String> {
+    let rustls = RustlsConfig::from_pem_file(&tls.cert_path, &tls.key_path)
+        .await
+        .map_err(|e| format!("Failed to load the TLS certificate: {}", e))?;
+
+    if tls.reload_interval_secs > 0 {
+        tokio::spawn(watch_certificates(rustls.clone(), tls.clone()));
+    }
+
+    let handle = Handle::new();
+    let shutdown_handle = handle.clone();
+    tokio::spawn(async move {
+        signal.await;
+        // the drain timeout is enforced by run_server
+        shutdown_handle.graceful_shutdown(None);
+    });
+
+    let addr = config.bind_addr();
+    tracing::info!("The server is up on address: {} (TLS)", addr);
+    axum_server::bind_rustls(addr, rustls)
+        .handle(handle)
+        .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+        .await
+        .map_err(|e| e.to_string())
+}
+
+fn modified(path: &str) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+// Polls the certificate and key, and swaps them in when either file changes
+async fn watch_certificates(rustls: RustlsConfig, tls: TlsConfig) {
+    let mut last = (modified(&tls.cert_path), modified(&tls.key_path));
+    let mut interval = tokio::time::interval(Duration::from_secs(tls.reload_interval_secs));
+
+    loop {
+        interval.tick().await;
+        let current = (modified(&tls.cert_path), modified(&tls.key_path));
+        if current == last {
+            continue;
+        }
+
+        match rustls.reload_from_pem_file(&tls.cert_path, &tls.key_path).await {
+            Ok(()) => {
+                tracing::info!("Reloaded the TLS certificate from {}", tls.cert_path);
+                last = current;
+            }
+            // keep serving the old certificate, e.g. while the files are half written
+            Err(e) => tracing::warn!("Failed to reload the TLS certificate: {}", e),
+        }
+    }
+}
@@ -0,0 +1,38 @@
+use std::{
+    sync::{atomic::{AtomicBool, Ordering}, Arc},
+    time::Duration,
+};
+use tokio::sync::Notify;
+
+async fn wait_for_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c().await.expect("Failed to listen for SIGINT");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Failed to listen for SIGTERM")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+}
+
+// Resolves when the server should stop accepting connections. On SIGTERM/SIGINT
+// /readyz starts failing first, so the load balancer stops routing to us during
+// `readiness_delay`, then `started` is notified so the drain timeout starts.
+pub async fn shutdown_signal(draining: Arc<AtomicBool>, readiness_delay: Duration, started: Arc<Notify>) {
+    wait_for_signal().await;
+    tracing::info!("Shutdown requested, draining connections");
+
+    draining.store(true, Ordering::SeqCst);
+    tokio::time::sleep(readiness_delay).await;
+    started.notify_one();
+}
@@ -0,0 +1,45 @@
+use axum::extract::FromRef;
+use metrics_exporter_prometheus::PrometheusHandle;
+use mongodb::Database;
+use std::sync::{atomic::AtomicBool, Arc};
+
+use crate::utils::{config::Config, events::EventBus, keyring::Keyring, oidc::OidcClient, password::Passwords, rate_limit::RateLimiter};
+
+// Shared state of the app, handlers pull out the parts they need with State<T>
+#[derive(Clone)]
+pub struct AppState {
+    pub config: Arc<Config>,
+    pub db: Database,
+    pub keyring: Arc<Keyring>,
+    pub oidc: Option<Arc<OidcClient>>,
+    pub passwords: Arc<Passwords>,
+    pub metrics: Option<PrometheusHandle>,
+    pub rate_limiter: Arc<RateLimiter>,
+    pub events: Arc<EventBus>,
+    // set once shutdown has started, /readyz reports not ready from then on
+    pub draining: Arc<AtomicBool>,
+}
+
+impl FromRef<AppState> for Database {
+    fn from_ref(state: &AppState) -> Self {
+        state.db.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<EventBus> {
+    fn from_ref(state: &AppState) -> Self {
+        state.events.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<Keyring> {
+    fn from_ref(state: &AppState) -> Self {
+        state.keyring.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<Passwords> {
+    fn from_ref(state: &AppState) -> Self {
+        state.passwords.clone()
+    }
+}
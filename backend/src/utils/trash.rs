@@ -0,0 +1,171 @@
+use bson::{doc, oid::ObjectId, DateTime, Document};
+use futures::TryStreamExt;
+use mongodb::Database;
+use std::time::Duration;
+
+use crate::models::task_model::Task;
+use crate::utils::config::TrashConfig;
+
+// Periodically purges the tasks that have been in the trash longer than the
+// retention, with their comments and notifications
+pub async fn trash_sweeper(db: Database, config: TrashConfig) {
+    let mut interval = tokio::time::interval(Duration::from_secs(config.sweep_interval_secs));
+
+    loop {
+        interval.tick().await;
+        if let Err(e) = purge_expired(&db, cutoff(DateTime::now(), &config)).await {
+            tracing::error!("Failed to purge the trash: {}", e);
+        }
+    }
+}
+
+// Tasks deleted before this are purged
+fn cutoff(now: DateTime, config: &TrashConfig) -> DateTime {
+    let retention_ms = i64::try_from(config.retention_days).unwrap_or(i64::MAX).saturating_mul(24 * 60 * 60 * 1000);
+    DateTime::from_millis(now.timestamp_millis().saturating_sub(retention_ms))
+}
+
+fn expired(cutoff: DateTime) -> Document {
+    doc! { "deleted_at": { "$lt": cutoff } }
+}
+
+// Matches the task only while it is still expired, a task restored since it
+// was found is left alone
+fn purge_filter(cutoff: DateTime, task_id: ObjectId) -> Document {
+    let mut filter = expired(cutoff);
+    filter.insert("_id", task_id);
+    filter
+}
+
+// What still points at a task once it is purged
+enum Cleanup {
+    Delete { collection: &'static str, filter: Document },
+    Update { collection: &'static str, filter: Document, update: Document },
+}
+
+fn cleanup(task: &Task, task_id: ObjectId) -> Vec<Cleanup> {
+    let mut cleanup = vec![
+        Cleanup::Delete { collection: "comment", filter: doc! { "task_id": task_id } },
+        // the inbox would link to a task that is gone, mentions included
+        Cleanup::Delete { collection: "notification", filter: doc! { "task_id": task_id } },
+        Cleanup::Update {
+            collection: "webhook_delivery",
+            filter: doc! { "task_id": task_id, "status": "pending" },
+            update: doc! { "$set": { "status": "cancelled" } },
+        },
+    ];
+    // the creator's task list still references it
+    if let Ok(user_obj_id) = ObjectId::parse_str(&task.user_id) {
+        cleanup.push(Cleanup::Update {
+            collection: "user",
+            filter: doc! { "_id": user_obj_id },
+            update: doc! { "$pull": { "tasks": task_id } },
+        });
+    }
+    cleanup
+}
+
+async fn purge_expired(db: &Database, cutoff: DateTime) -> Result<(), mongodb::error::Error> {
+    let collection = db.collection::<Task>("task");
+
+    let tasks: Vec<Task> = collection.find(expired(cutoff)).await?.try_collect().await?;
+    let mut purged = 0;
+    for task in tasks {
+        let Some(task_id) = task.task_id else { continue };
+        if collection.delete_one(purge_filter(cutoff, task_id)).await?.deleted_count == 0 {
+            continue;
+        }
+        purged += 1;
+
+        for step in cleanup(&task, task_id) {
+            match step {
+                Cleanup::Delete { collection, filter } => {
+                    db.collection::<Document>(collection).delete_many(filter).await?;
+                }
+                Cleanup::Update { collection, filter, update } => {
+                    db.collection::<Document>(collection).update_many(filter, update).await?;
+                }
+            }
+        }
+    }
+
+    if purged > 0 {
+        tracing::info!("Purged {} tasks from the trash", purged);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn task(user_id: &str) -> Task {
+        Task {
+            task_id: Some(ObjectId::new()),
+            taskname: "Old".to_string(),
+            description: String::new(),
+            mentions: vec![],
+            status: "Done".to_string(),
+            user_id: user_id.to_string(),
+            project_id: None,
+            created_by: user_id.to_string(),
+            assignee_ids: vec![],
+            comment_count: 0,
+            due_at: None,
+            due_notified: false,
+            deleted_at: Some(DateTime::from_millis(0)),
+            deleted_by: Some(user_id.to_string()),
+        }
+    }
+
+    #[test]
+    fn the_cutoff_is_the_retention_before_now() {
+        let now = DateTime::from_millis(1_792_368_000_000);
+        let config = TrashConfig { retention_days: 30, ..TrashConfig::default() };
+        assert_eq!(cutoff(now, &config).timestamp_millis(), 1_792_368_000_000 - 30 * 24 * 60 * 60 * 1000);
+
+        // a retention past the epoch doesn't wrap around
+        let config = TrashConfig { retention_days: u64::MAX, ..TrashConfig::default() };
+        assert!(cutoff(now, &config) < now);
+    }
+
+    #[test]
+    fn only_a_task_still_in_the_trash_is_purged() {
+        let cutoff = DateTime::from_millis(1_000);
+        let task_id = ObjectId::new();
+        let filter = purge_filter(cutoff, task_id);
+
+        assert_eq!(filter.get_object_id("_id").unwrap(), task_id);
+        // restoring unsets deleted_at, so a restored task no longer matches
+        assert_eq!(filter.get_document("deleted_at").unwrap(), &doc! { "$lt": cutoff });
+    }
+
+    #[test]
+    fn purging_cleans_up_what_points_at_the_task() {
+        let task = task(&ObjectId::new().to_hex());
+        let task_id = task.task_id.unwrap();
+
+        let mut deleted = vec![];
+        let mut updated = vec![];
+        for step in cleanup(&task, task_id) {
+            match step {
+                Cleanup::Delete { collection, filter } => {
+                    assert_eq!(filter.get_object_id("task_id").unwrap(), task_id);
+                    deleted.push(collection);
+                }
+                Cleanup::Update { collection, filter, update } => {
+                    if collection == "webhook_delivery" {
+                        assert_eq!(filter, doc! { "task_id": task_id, "status": "pending" });
+                        assert_eq!(update, doc! { "$set": { "status": "cancelled" } });
+                    }
+                    updated.push(collection);
+                }
+            }
+        }
+        assert_eq!(deleted, vec!["comment", "notification"]);
+        assert_eq!(updated, vec!["webhook_delivery", "user"]);
+
+        // a creator id that isn't an ObjectId has no task list to update
+        assert_eq!(cleanup(&self::task("legacy"), task_id).len(), 3);
+    }
+}
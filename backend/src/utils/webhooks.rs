@@ -0,0 +1,549 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use bson::{doc, oid::ObjectId, DateTime};
+use futures::{stream, StreamExt, TryStreamExt};
+use mongodb::{options::ReturnDocument, Database};
+use reqwest::{dns::{Addrs, Name, Resolve, Resolving}, Url};
+use ring::{hmac, rand::{SecureRandom, SystemRandom}};
+use serde_json::{json, Value};
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::net::lookup_host;
+
+use crate::controller::task_controller::task_response;
+use crate::models::{task_model::Task, webhook_model::{DeliveryAttempt, Webhook, WebhookDelivery}};
+use crate::utils::{authorization::task_viewers, config::WebhooksConfig, events::TaskEventKind};
+
+// The events a webhook can subscribe to, "ping" is only sent on request
+pub const EVENTS: [&str; 4] = ["task.created", "task.updated", "task.deleted", "task.restored"];
+
+// how much of the response body is kept in the delivery log
+const SNIPPET_BYTES: usize = 512;
+
+pub fn generate_secret() -> Result<String, String> {
+    let mut bytes = [0u8; 32];
+    SystemRandom::new()
+        .fill(&mut bytes)
+        .map_err(|_| "Failed to generate random bytes".to_string())?;
+    Ok(format!("whsec_{}", URL_SAFE_NO_PAD.encode(bytes)))
+}
+
+// `sha256=` and the hex HMAC-SHA256 of `{timestamp}.{body}` with the webhook's secret
+pub fn signature(secret: &str, timestamp: i64, body: &str) -> String {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes());
+    let tag = hmac::sign(&key, format!("{}.{}", timestamp, body).as_bytes());
+    let hex: String = tag.as_ref().iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!("sha256={}", hex)
+}
+
+// Whether an address can be reached from the internet. Loopback, private,
+// link-local (where cloud metadata services live) and the other special ranges
+// are refused, so a webhook can't be used to read internal services.
+pub fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_unspecified()
+                || ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_multicast()
+                || a == 0
+                // shared address space, 100.64.0.0/10
+                || (a == 100 && (64..128).contains(&b))
+                // benchmarking, 198.18.0.0/15
+                || (a == 198 && (b == 18 || b == 19))
+                // reserved, 240.0.0.0/4
+                || a >= 240)
+        }
+        IpAddr::V6(ip) => {
+            let segments = ip.segments();
+            if let Some(ip) = ip.to_ipv4_mapped() {
+                return is_public(IpAddr::V4(ip));
+            }
+            // NAT64, 64:ff9b::/96 reaches the IPv4 address in the last 32 bits
+            if segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0] {
+                let [.., high, low] = segments;
+                return is_public(IpAddr::V4(((u32::from(high) << 16) | u32::from(low)).into()));
+            }
+            // ::/96 holds the unspecified and loopback addresses and the old IPv4-compatible ones
+            !(segments[..6] == [0; 6]
+                || ip.is_multicast()
+                || ip.is_unique_local()
+                || ip.is_unicast_link_local())
+        }
+    }
+}
+
+fn host_allowed(host: &str, config: &WebhooksConfig) -> bool {
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    config.allowed_hosts.iter().any(|allowed| allowed.eq_ignore_ascii_case(host))
+}
+
+fn public_addresses(host: &str, addresses: impl Iterator<Item = SocketAddr>) -> Result<Vec<SocketAddr>, String> {
+    let addresses: Vec<SocketAddr> = addresses.collect();
+    match addresses.iter().find(|address| !is_public(address.ip())) {
+        Some(address) if host.trim_start_matches('[').trim_end_matches(']') == address.ip().to_string() => {
+            Err(format!("{} is not a public address", address.ip()))
+        }
+        Some(address) => Err(format!("{} resolves to {}, which is not a public address", host, address.ip())),
+        None if addresses.is_empty() => Err(format!("{} doesn't resolve to any address", host)),
+        None => Ok(addresses),
+    }
+}
+
+// Refuses a URL whose host is or resolves to an address that isn't public,
+// unless the host is one of webhooks.allowed_hosts
+pub async fn check_destination(url: &Url, config: &WebhooksConfig) -> Result<(), String> {
+    let host = url.host_str().ok_or_else(|| "The URL has no host".to_string())?;
+    if host_allowed(host, config) {
+        return Ok(());
+    }
+    let port = url.port_or_known_default().unwrap_or(443);
+    let addresses = lookup_host(format!("{}:{}", host, port))
+        .await
+        .map_err(|e| format!("{} can't be resolved: {}", host, e))?;
+    public_addresses(host, addresses).map(|_| ())
+}
+
+// The resolver of the delivery client. It refuses private addresses at
+// connect time too, so a host name that resolved to a public address when it
+// was checked can't be switched to an internal one afterwards.
+struct PublicResolver {
+    config: WebhooksConfig,
+}
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let allowed = host_allowed(name.as_str(), &self.config);
+        Box::pin(async move {
+            let addresses = lookup_host((name.as_str(), 0)).await?;
+            let addresses = if allowed { addresses.collect() } else { public_addresses(name.as_str(), addresses)? };
+            Ok(Box::new(addresses.into_iter()) as Addrs)
+        })
+    }
+}
+
+fn http_client(config: &WebhooksConfig) -> reqwest::Result<reqwest::Client> {
+    reqwest::Client::builder()
+        .timeout(Duration::from_secs(config.timeout_secs))
+        .redirect(reqwest::redirect::Policy::none())
+        .dns_resolver(Arc::new(PublicResolver { config: config.clone() }))
+        .build()
+}
+
+// Queues an event for a webhook, `data` is merged into the payload
+pub async fn enqueue(
+    db: &Database,
+    webhook_id: ObjectId,
+    task_id: Option<ObjectId>,
+    event: &str,
+    data: Value,
+) -> Result<ObjectId, mongodb::error::Error> {
+    let delivery = new_delivery(webhook_id, task_id, event, data);
+    let delivery_id = delivery.delivery_id;
+    db.collection::<WebhookDelivery>("webhook_delivery").insert_one(delivery).await?;
+    Ok(delivery_id)
+}
+
+fn new_delivery(webhook_id: ObjectId, task_id: Option<ObjectId>, event: &str, data: Value) -> WebhookDelivery {
+    let delivery_id = ObjectId::new();
+    let now = DateTime::now();
+
+    let mut payload = json!({
+        "id": delivery_id.to_hex(),
+        "event": event,
+        "created_at": now.try_to_rfc3339_string().unwrap_or_default(),
+    });
+    if let (Some(payload), Value::Object(data)) = (payload.as_object_mut(), data) {
+        payload.extend(data);
+    }
+
+    WebhookDelivery {
+        delivery_id,
+        webhook_id,
+        task_id,
+        event: event.to_string(),
+        payload: payload.to_string(),
+        status: "pending".to_string(),
+        attempts: vec![],
+        next_attempt_at: now,
+        created_at: now,
+    }
+}
+
+// Queues a task change for the active webhooks of the users who can see the
+// task. The handlers call it with the change saved, next to publishing it on
+// the event bus, which drops events for subscribers that fall behind.
+pub async fn queue_task_event(db: &Database, kind: TaskEventKind, task_id: ObjectId, task: &Task) {
+    if let Err(e) = queue_event(db, kind, task_id, task).await {
+        tracing::error!("Failed to queue the webhook deliveries of task {}: {}", task_id, e);
+    }
+}
+
+async fn queue_event(db: &Database, kind: TaskEventKind, task_id: ObjectId, task: &Task) -> Result<(), String> {
+    let viewers = task_viewers(db, task).await.map_err(|(_, e)| e)?;
+    let kind = kind.as_str();
+
+    let webhooks: Vec<Webhook> = db
+        .collection::<Webhook>("webhook")
+        .find(doc! {
+            "user_id": { "$in": &viewers },
+            "active": true,
+            "$or": [{ "events": { "$size": 0 } }, { "events": kind }],
+        })
+        .await
+        .map_err(|e| e.to_string())?
+        .try_collect()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let data = json!({ "task": task_response(task_id, task.clone()) });
+    for webhook_id in webhooks.into_iter().filter_map(|webhook| webhook.webhook_id) {
+        enqueue(db, webhook_id, Some(task_id), kind, data.clone()).await.map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+// Sends the due deliveries of the queue, up to webhooks.concurrency at a time.
+// The queue is in MongoDB, so pending deliveries survive a restart and several
+// instances share the work.
+pub async fn delivery_worker(db: Database, config: WebhooksConfig) {
+    let http = match http_client(&config) {
+        Ok(http) => http,
+        Err(e) => {
+            tracing::error!("Webhooks are not sent, failed to build the HTTP client: {}", e);
+            return;
+        }
+    };
+    let mut interval = tokio::time::interval(Duration::from_secs(config.poll_interval_secs));
+
+    let (db, http, config) = (&db, &http, &config);
+
+    loop {
+        interval.tick().await;
+        // a delivery is only claimed once a slot is free, so its lease
+        // doesn't run out while it waits
+        let due = stream::unfold((), move |()| async move {
+            match claim_delivery(db, config).await {
+                Ok(delivery) => delivery.map(|delivery| (delivery, ())),
+                Err(e) => {
+                    tracing::error!("Failed to read the webhook queue: {}", e);
+                    None
+                }
+            }
+        });
+        due.for_each_concurrent(config.concurrency, |delivery| deliver(db, http, config, delivery)).await;
+    }
+}
+
+// Takes the next due delivery and pushes its next attempt past the time the
+// attempt can take, so another instance doesn't send it too and it is retried
+// if this one stops halfway
+async fn claim_delivery(db: &Database, config: &WebhooksConfig) -> Result<Option<WebhookDelivery>, mongodb::error::Error> {
+    let now = DateTime::now();
+    let lease_ms = (config.timeout_secs as i64 + 30) * 1000;
+    db.collection::<WebhookDelivery>("webhook_delivery")
+        .find_one_and_update(
+            doc! { "status": "pending", "next_attempt_at": { "$lte": now } },
+            doc! { "$set": { "next_attempt_at": DateTime::from_millis(now.timestamp_millis() + lease_ms) } },
+        )
+        .sort(doc! { "next_attempt_at": 1 })
+        .await
+}
+
+async fn deliver(db: &Database, http: &reqwest::Client, config: &WebhooksConfig, delivery: WebhookDelivery) {
+    let deliveries = db.collection::<WebhookDelivery>("webhook_delivery");
+    let webhooks = db.collection::<Webhook>("webhook");
+
+    let webhook = match webhooks.find_one(doc! { "_id": delivery.webhook_id }).await {
+        Ok(Some(webhook)) if webhook.active => webhook,
+        Ok(_) => {
+            if let Err(e) = deliveries
+                .update_one(doc! { "_id": delivery.delivery_id }, doc! { "$set": { "status": "cancelled" } })
+                .await
+            {
+                tracing::error!("Failed to cancel the webhook delivery: {}", e);
+            }
+            return;
+        }
+        Err(e) => {
+            // the lease runs out and it is tried again
+            tracing::error!("Failed to load the webhook: {}", e);
+            return;
+        }
+    };
+
+    let attempt = send(http, config, &webhook, &delivery).await;
+    let outcome = outcome(&attempt, delivery.attempts.len() as u32 + 1, config);
+
+    let set = match outcome {
+        Outcome::Succeeded => doc! { "status": "succeeded" },
+        Outcome::Retry(next_attempt_at) => doc! { "next_attempt_at": next_attempt_at },
+        Outcome::Failed => doc! { "status": "failed" },
+    };
+    let attempt = bson::to_bson(&attempt).unwrap_or_default();
+    if let Err(e) = deliveries
+        .update_one(doc! { "_id": delivery.delivery_id }, doc! { "$set": set, "$push": { "attempts": attempt } })
+        .await
+    {
+        tracing::error!("Failed to record the webhook delivery attempt: {}", e);
+    }
+
+    let result = if outcome == Outcome::Succeeded {
+        webhooks
+            .update_one(doc! { "_id": delivery.webhook_id }, doc! { "$set": { "consecutive_failures": 0 } })
+            .await
+            .map(|_| ())
+    } else {
+        record_failure(db, delivery.webhook_id, config).await
+    };
+    if let Err(e) = result {
+        tracing::error!("Failed to update the webhook after a delivery: {}", e);
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Outcome {
+    Succeeded,
+    // retried at this time, with exponential backoff
+    Retry(DateTime),
+    // out of attempts
+    Failed,
+}
+
+// Where a delivery goes after an attempt, `attempt_count` includes it
+fn outcome(attempt: &DeliveryAttempt, attempt_count: u32, config: &WebhooksConfig) -> Outcome {
+    if attempt.status_code.is_some_and(|status| (200..300).contains(&status)) {
+        return Outcome::Succeeded;
+    }
+    if attempt_count >= config.max_attempts {
+        return Outcome::Failed;
+    }
+    let delay_secs = config.retry_base_secs.saturating_mul(1 << (attempt_count - 1).min(16));
+    let next_attempt_ms = attempt.at.timestamp_millis().saturating_add((delay_secs as i64).saturating_mul(1000));
+    Outcome::Retry(DateTime::from_millis(next_attempt_ms))
+}
+
+async fn send(http: &reqwest::Client, config: &WebhooksConfig, webhook: &Webhook, delivery: &WebhookDelivery) -> DeliveryAttempt {
+    let at = DateTime::now();
+    let timestamp = at.timestamp_millis() / 1000;
+    let started = Instant::now();
+
+    // checked again as the address may have changed since the webhook was
+    // registered, the client's resolver covers the lookup of the request itself
+    let destination = match Url::parse(&webhook.url) {
+        Ok(url) => check_destination(&url, config).await,
+        Err(e) => Err(e.to_string()),
+    };
+    if let Err(e) = destination {
+        return DeliveryAttempt {
+            at,
+            status_code: None,
+            latency_ms: started.elapsed().as_millis() as i64,
+            response_snippet: String::new(),
+            error: Some(e),
+        };
+    }
+
+    let response = http
+        .post(&webhook.url)
+        .header("Content-Type", "application/json")
+        .header("User-Agent", "RustManager-Webhooks")
+        .header("X-Webhook-Id", delivery.delivery_id.to_hex())
+        .header("X-Webhook-Event", &delivery.event)
+        .header("X-Webhook-Timestamp", timestamp.to_string())
+        .header("X-Webhook-Signature", signature(&webhook.secret, timestamp, &delivery.payload))
+        .body(delivery.payload.clone())
+        .send()
+        .await;
+
+    let (status_code, response_snippet, error) = match response {
+        Ok(mut response) => {
+            let status = response.status().as_u16();
+            let mut body = Vec::new();
+            while body.len() < SNIPPET_BYTES {
+                match response.chunk().await {
+                    Ok(Some(chunk)) => body.extend_from_slice(&chunk),
+                    _ => break,
+                }
+            }
+            body.truncate(SNIPPET_BYTES);
+            (Some(status), String::from_utf8_lossy(&body).into_owned(), None)
+        }
+        Err(e) => (None, String::new(), Some(e.to_string())),
+    };
+
+    DeliveryAttempt {
+        at,
+        status_code,
+        latency_ms: started.elapsed().as_millis() as i64,
+        response_snippet,
+        error,
+    }
+}
+
+// Whether a webhook is turned off after this many failed attempts in a row
+fn past_failure_limit(consecutive_failures: i32, config: &WebhooksConfig) -> bool {
+    i64::from(consecutive_failures) >= i64::from(config.disable_after_failures)
+}
+
+async fn record_failure(db: &Database, webhook_id: ObjectId, config: &WebhooksConfig) -> Result<(), mongodb::error::Error> {
+    let webhooks = db.collection::<Webhook>("webhook");
+    let webhook = webhooks
+        .find_one_and_update(doc! { "_id": webhook_id }, doc! { "$inc": { "consecutive_failures": 1 } })
+        .return_document(ReturnDocument::After)
+        .await?;
+    if !webhook.is_some_and(|webhook| webhook.active && past_failure_limit(webhook.consecutive_failures, config)) {
+        return Ok(());
+    }
+
+    let disabled = webhooks
+        .update_one(
+            doc! { "_id": webhook_id, "active": true },
+            doc! { "$set": { "active": false, "disabled_at": DateTime::now() } },
+        )
+        .await?;
+    if disabled.modified_count > 0 {
+        tracing::warn!("Disabled webhook {} after {} failed attempts in a row", webhook_id, config.disable_after_failures);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{extract::State, http::{HeaderMap, StatusCode}, routing::post, Router};
+    use std::{str::FromStr, sync::{atomic::{AtomicU16, Ordering}, Mutex}};
+
+    // A local receiver answering with the status the test sets and keeping
+    // the requests it got
+    #[derive(Clone)]
+    struct Receiver {
+        url: String,
+        status: Arc<AtomicU16>,
+        requests: Arc<Mutex<Vec<(HeaderMap, String)>>>,
+    }
+
+    async fn receiver() -> Receiver {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let receiver = Receiver {
+            url: format!("http://{}/hook", listener.local_addr().unwrap()),
+            status: Arc::new(AtomicU16::new(200)),
+            requests: Arc::new(Mutex::new(vec![])),
+        };
+
+        let app = Router::new()
+            .route("/hook", post(|State(receiver): State<Receiver>, headers: HeaderMap, body: String| async move {
+                receiver.requests.lock().unwrap().push((headers, body));
+                StatusCode::from_u16(receiver.status.load(Ordering::SeqCst)).unwrap()
+            }))
+            .with_state(receiver.clone());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        receiver
+    }
+
+    fn webhook(url: &str) -> Webhook {
+        Webhook {
+            webhook_id: Some(ObjectId::new()),
+            user_id: ObjectId::new().to_hex(),
+            url: url.to_string(),
+            secret: generate_secret().unwrap(),
+            events: vec![],
+            active: true,
+            consecutive_failures: 0,
+            created_at: DateTime::now(),
+            disabled_at: None,
+        }
+    }
+
+    fn local_config() -> WebhooksConfig {
+        WebhooksConfig {
+            allow_http: true,
+            allowed_hosts: vec!["127.0.0.1".to_string()],
+            max_attempts: 3,
+            retry_base_secs: 30,
+            disable_after_failures: 3,
+            ..WebhooksConfig::default()
+        }
+    }
+
+    fn header<'a>(headers: &'a HeaderMap, name: &str) -> &'a str {
+        headers.get(name).unwrap().to_str().unwrap()
+    }
+
+    #[tokio::test]
+    async fn sends_signed_deliveries_and_retries_with_backoff() {
+        let receiver = receiver().await;
+        let config = local_config();
+        let http = http_client(&config).unwrap();
+        let mut webhook = webhook(&receiver.url);
+        let delivery = new_delivery(webhook.webhook_id.unwrap(), Some(ObjectId::new()), "task.updated", json!({ "task": { "taskname": "Write tests" } }));
+
+        receiver.status.store(500, Ordering::SeqCst);
+        let mut retry_at = vec![];
+        for attempt_count in 1..=3 {
+            let attempt = send(&http, &config, &webhook, &delivery).await;
+            assert_eq!(attempt.status_code, Some(500));
+
+            let (headers, body) = receiver.requests.lock().unwrap().last().cloned().unwrap();
+            let timestamp: i64 = header(&headers, "X-Webhook-Timestamp").parse().unwrap();
+            assert_eq!(body, delivery.payload);
+            assert_eq!(header(&headers, "X-Webhook-Id"), delivery.delivery_id.to_hex());
+            assert_eq!(header(&headers, "X-Webhook-Event"), "task.updated");
+            assert_eq!(header(&headers, "X-Webhook-Signature"), signature(&webhook.secret, timestamp, &body));
+
+            match outcome(&attempt, attempt_count, &config) {
+                Outcome::Retry(at) => retry_at.push(at.timestamp_millis() - attempt.at.timestamp_millis()),
+                Outcome::Failed => assert_eq!(attempt_count, config.max_attempts),
+                Outcome::Succeeded => panic!("a 500 counted as delivered"),
+            }
+
+            // the webhook is turned off once the failures in a row reach the limit
+            webhook.consecutive_failures += 1;
+            assert_eq!(past_failure_limit(webhook.consecutive_failures, &config), attempt_count >= 3);
+        }
+        assert_eq!(retry_at, vec![30_000, 60_000]);
+        assert_eq!(receiver.requests.lock().unwrap().len(), 3);
+
+        receiver.status.store(204, Ordering::SeqCst);
+        let attempt = send(&http, &config, &webhook, &delivery).await;
+        assert_eq!(outcome(&attempt, 1, &config), Outcome::Succeeded);
+    }
+
+    #[tokio::test]
+    async fn refuses_private_addresses_unless_allowed() {
+        let receiver = receiver().await;
+        let config = WebhooksConfig { allowed_hosts: vec![], ..local_config() };
+        let http = http_client(&config).unwrap();
+
+        let attempt = send(&http, &config, &webhook(&receiver.url), &new_delivery(ObjectId::new(), None, "ping", json!({}))).await;
+        assert_eq!(attempt.status_code, None);
+        assert!(attempt.error.unwrap().contains("not a public address"));
+        assert!(receiver.requests.lock().unwrap().is_empty());
+
+        // a name checked earlier is checked again when the request connects
+        let resolver = PublicResolver { config: config.clone() };
+        assert!(resolver.resolve(Name::from_str("localhost").unwrap()).await.is_err());
+        let resolver = PublicResolver { config: WebhooksConfig { allowed_hosts: vec!["localhost".to_string()], ..config } };
+        assert!(resolver.resolve(Name::from_str("localhost").unwrap()).await.is_ok());
+    }
+
+    #[test]
+    fn only_public_addresses_pass() {
+        for private in [
+            "127.0.0.1", "10.1.2.3", "172.16.0.1", "192.168.1.1", "169.254.169.254", "100.100.100.200",
+            "0.0.0.0", "255.255.255.255", "::1", "::", "fe80::1", "fd00:ec2::254", "::ffff:127.0.0.1",
+            "64:ff9b::a9fe:a9fe",
+        ] {
+            assert!(!is_public(private.parse().unwrap()), "{} passed", private);
+        }
+        for public in ["93.184.216.34", "1.1.1.1", "2606:4700:4700::1111", "64:ff9b::101:101"] {
+            assert!(is_public(public.parse().unwrap()), "{} was refused", public);
+        }
+    }
+}